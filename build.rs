@@ -0,0 +1,104 @@
+//! Find libreadline and detect its version.
+//!
+//! The library (libedit with the `libedit` feature) is found with pkg-config, unless `READLINE_LIB_DIR` is set.
+//! Nothing is linked with the `pure-rust` and `dynamic` features.
+//! `READLINE_STATIC` links it statically and `READLINE_INCLUDE_DIR` gives the directory of `readline/readline.h`.
+//! APIs missing from older versions are only compiled when the matching cfg flag is emitted.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+// (cfg flag, first readline version with it)
+const FEATURES: &[(&str, (u32, u32))] = &[
+    ("rl_clear_visible_line", (7, 0)),
+    ("bracketed_paste", (7, 0)),
+];
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    for var in &["READLINE_LIB_DIR", "READLINE_INCLUDE_DIR", "READLINE_STATIC"] {
+        println!("cargo:rerun-if-env-changed={}", var);
+    }
+    for &(flag, _) in FEATURES {
+        println!("cargo:rustc-check-cfg=cfg({})", flag);
+    }
+
+    if env::var_os("CARGO_FEATURE_PURE_RUST").is_some() || env::var_os("CARGO_FEATURE_DYNAMIC").is_some() {
+        // nothing to link: the Rust implementation has all the features,
+        // and the functions loaded at runtime fall back when they are missing
+        for &(flag, _) in FEATURES {
+            println!("cargo:rustc-cfg={}", flag);
+        }
+        return;
+    }
+
+    let libedit = env::var_os("CARGO_FEATURE_LIBEDIT").is_some();
+    let (lib, package) = if libedit { ("edit", "libedit") } else { ("readline", "readline") };
+    let statik = env::var_os("READLINE_STATIC").is_some();
+    let mut include_dirs: Vec<PathBuf> = env::var_os("READLINE_INCLUDE_DIR").map(PathBuf::from).into_iter().collect();
+    if let Some(lib_dir) = env::var_os("READLINE_LIB_DIR").filter(|dir| !dir.is_empty()) {
+        let lib_dir = PathBuf::from(lib_dir);
+        if !lib_dir.is_dir() {
+            fail(&format!("READLINE_LIB_DIR={} is not a directory", lib_dir.display()));
+        }
+        println!("cargo:rustc-link-search=native={}", lib_dir.display());
+        if statik {
+            println!("cargo:rustc-link-lib=static={}", lib);
+            // the static library needs the terminal functions of (n)curses
+            println!("cargo:rustc-link-lib=ncurses");
+        } else {
+            println!("cargo:rustc-link-lib={}", lib);
+        }
+        // <prefix>/lib -> <prefix>/include
+        if let Some(prefix) = lib_dir.parent() {
+            include_dirs.push(prefix.join("include"));
+        }
+    } else {
+        match pkg_config::Config::new().statik(statik).probe(package) {
+            Ok(library) => include_dirs.extend(library.include_paths),
+            Err(err) => fail(&format!("could not find lib{} with pkg-config ({}).\n\
+                Install the development package of {} (e.g. lib{}-dev or {}-devel), \
+                or set READLINE_LIB_DIR to the directory containing lib{}", lib, err, package, lib, package, lib)),
+        }
+    }
+    if libedit {
+        // the features depend on the version of GNU readline
+        return;
+    }
+    include_dirs.push(PathBuf::from("/usr/local/include"));
+    include_dirs.push(PathBuf::from("/usr/include"));
+
+    match include_dirs.iter().filter_map(|dir| version(dir)).next() {
+        Some(version) => {
+            for &(flag, since) in FEATURES {
+                if version >= since {
+                    println!("cargo:rustc-cfg={}", flag);
+                }
+            }
+        }
+        None => println!("cargo:warning=readline/readline.h not found, \
+            set READLINE_INCLUDE_DIR to enable the APIs of readline 7.0 and later"),
+    }
+}
+
+/// Return the (major, minor) version of readline from the `readline/readline.h` header found in `dir`.
+fn version(dir: &Path) -> Option<(u32, u32)> {
+    let header = fs::read_to_string(dir.join("readline").join("readline.h")).ok()?;
+    let define = |name: &str| header.lines()
+        .filter_map(|line| {
+            let mut tokens = line.split_whitespace();
+            match (tokens.next(), tokens.next(), tokens.next()) {
+                (Some("#define"), Some(n), Some(value)) if n == name => value.parse().ok(),
+                _ => None,
+            }
+        })
+        .next();
+    Some((define("RL_VERSION_MAJOR")?, define("RL_VERSION_MINOR")?))
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("error: {}", message);
+    process::exit(1);
+}
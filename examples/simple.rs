@@ -1,38 +1,22 @@
-#![feature(libc)]
-
-extern crate readline as rl;
-extern crate libc;
+use readline as rl;
 
 use std::io::{BufRead,BufReader};
 use std::fs::File;
 use std::path::Path;
-use std::ffi::CStr;
-use std::str;
 
-fn complete(text: String) -> Vec<String> {
+fn complete(context: &rl::CompletionContext) -> Vec<String> {
     let path = Path::new("/usr/share/dict/words");
-    let file = BufReader::new(File::open(&path).unwrap());
+    let file = match File::open(path) {
+        Ok(file) => BufReader::new(file),
+        Err(_) => return Vec::new(),
+    };
     let mut entries: Vec<String> = Vec::new();
-    for line in file.lines() {
-        let word = line.unwrap();
-        if (&word).starts_with(&text) {
+    for word in file.lines().map_while(Result::ok) {
+        if word.starts_with(&context.word) {
             entries.push(word);
         }
     }
-    return entries;
-}
-
-extern fn rl_compentry_func(text: *const i8, state: i32) -> *const i8 {
-    if state == 0 {
-        let txt = unsafe { CStr::from_ptr(text).to_bytes() };
-        let entries = complete(str::from_utf8(txt).unwrap().to_string());
-        rl::set_compentries(entries);
-    }
-    rl::get_compentry(state as usize)
-}
-
-extern fn my_attempted_completion_function(text: *const i8, _start: i32, _end: i32) -> *mut *const i8 {
-    return rl::rl_completion_matches(text, rl_compentry_func)
+    entries
 }
 
 // cargo run --example simple
@@ -41,7 +25,7 @@ pub fn main() {
     //println!("{}", rl::rl_readline_version())
     println!("{}", rl::rl_library_version());
 
-    rl::set_rl_attempted_completion_function(Some(my_attempted_completion_function));
+    rl::set_completer(Some(Box::new(complete)));
 
     loop {
         match rl::readline("> ") {
@@ -52,9 +36,9 @@ pub fn main() {
                 //println!("{}", rl::history_get(-2));
             },
             _ => {
-                println!("");
+                println!();
                 break
             }
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,91 @@
+//! Caching of slow completers.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use super::completion::{Completer, CompletionContext};
+
+// (line before the word, word) -> (time, candidates)
+type Cache = HashMap<(String, String), (Instant, Vec<String>)>;
+
+/// A `Completer` remembering the candidates of another one for `ttl`.
+///
+/// Candidates are keyed by the line before the word being completed and by the word itself,
+/// so pressing TAB twice (to list the matches) or completing the same word again does not call the inner completer.
+pub struct CachedCompleter<C> {
+    completer: C,
+    ttl: Duration,
+    entries: RefCell<Cache>,
+}
+
+impl<C: Completer> CachedCompleter<C> {
+    /// Cache the candidates of `completer` for `ttl`.
+    pub fn new(completer: C, ttl: Duration) -> CachedCompleter<C> {
+        CachedCompleter { completer, ttl, entries: RefCell::new(HashMap::new()) }
+    }
+
+    /// Forget all the cached candidates.
+    pub fn clear(&self) {
+        self.entries.borrow_mut().clear();
+    }
+}
+
+impl<C: Completer> CachedCompleter<C> {
+    /// Complete at the time `now`.
+    fn complete_at(&self, context: &CompletionContext, now: Instant) -> Vec<String> {
+        let key = (context.line[..context.start].to_string(), context.word.clone());
+        if let Some(&(time, ref entries)) = self.entries.borrow().get(&key) {
+            if now.duration_since(time) < self.ttl {
+                return entries.clone();
+            }
+        }
+        let entries = self.completer.complete(context);
+        let mut cache = self.entries.borrow_mut();
+        let ttl = self.ttl;
+        cache.retain(|_, &mut (time, _)| now.duration_since(time) < ttl);
+        cache.insert(key, (now, entries.clone()));
+        entries
+    }
+}
+
+impl<C: Completer> Completer for CachedCompleter<C> {
+    fn complete(&self, context: &CompletionContext) -> Vec<String> {
+        self.complete_at(context, Instant::now())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::time::{Duration, Instant};
+
+    use super::CachedCompleter;
+    use super::super::completion::{Completer, CompletionContext};
+
+    #[test]
+    fn cached_completer() {
+        let calls = Cell::new(0);
+        let completer = CachedCompleter::new(|context: &CompletionContext| {
+            calls.set(calls.get() + 1);
+            vec![format!("{}1", context.word)]
+        }, Duration::from_millis(100));
+
+        let start = Instant::now();
+        let context = CompletionContext::new("get a", 4, 5);
+        assert_eq!(completer.complete_at(&context, start), vec!["a1"]);
+        assert_eq!(completer.complete_at(&context, start + Duration::from_millis(99)), vec!["a1"]);
+        assert_eq!(calls.get(), 1);
+        // another word, another context
+        assert_eq!(completer.complete_at(&CompletionContext::new("get b", 4, 5), start), vec!["b1"]);
+        assert_eq!(completer.complete_at(&CompletionContext::new("set a", 4, 5), start), vec!["a1"]);
+        assert_eq!(calls.get(), 3);
+
+        // expired
+        assert_eq!(completer.complete_at(&context, start + Duration::from_millis(100)), vec!["a1"]);
+        assert_eq!(calls.get(), 4);
+        completer.clear();
+        completer.complete(&context);
+        assert_eq!(calls.get(), 5);
+    }
+}
@@ -0,0 +1,189 @@
+//! Completion of nested commands, flags and arguments.
+
+use super::completion::{Completer, CompletionContext};
+use super::filename::FilenameCompleter;
+use super::matcher::Matcher;
+
+/// A function returning the values for the word being completed.
+pub type DynamicValues = Box<dyn Fn(&str) -> Vec<String>>;
+
+/// The kind of value expected by an argument or a flag.
+pub enum Arg {
+    /// One of the given values.
+    Values(Vec<String>),
+    /// A filename.
+    Path,
+    /// Values given by a function receiving the word being completed.
+    Dynamic(DynamicValues),
+}
+
+impl Arg {
+    /// One of `values`.
+    pub fn values(values: &[&str]) -> Arg {
+        Arg::Values(values.iter().map(|v| v.to_string()).collect())
+    }
+
+    fn complete(&self, text: &str, matcher: Matcher) -> Vec<String> {
+        match *self {
+            Arg::Values(ref values) => matcher.filter(text, values.iter().cloned()),
+            Arg::Path => FilenameCompleter::new().complete(&CompletionContext::new(text, 0, text.len())),
+            Arg::Dynamic(ref f) => matcher.filter(text, f(text)),
+        }
+    }
+}
+
+/// A command with its subcommands, flags and positional arguments.
+///
+/// ```no_run
+/// use readline::{Arg, CommandTree};
+///
+/// let tree = CommandTree::new("")
+///     .subcommand(CommandTree::new("db")
+///         .subcommand(CommandTree::new("connect").flag("--ssl").arg(Arg::Dynamic(Box::new(|_| vec!["localhost".to_string()]))))
+///         .subcommand(CommandTree::new("list")))
+///     .subcommand(CommandTree::new("set").arg(Arg::values(&["editor", "pager"])).arg(Arg::Path));
+/// readline::set_completer(Some(Box::new(tree)));
+/// ```
+pub struct CommandTree {
+    name: String,
+    subcommands: Vec<CommandTree>,
+    flags: Vec<(String, Option<Arg>)>,
+    args: Vec<Arg>,
+    matcher: Option<Matcher>,
+}
+
+impl CommandTree {
+    /// Create a command named `name` (the name of the root command is ignored).
+    pub fn new(name: &str) -> CommandTree {
+        CommandTree { name: name.to_string(), subcommands: Vec::new(), flags: Vec::new(), args: Vec::new(), matcher: None }
+    }
+
+    /// Add a subcommand.
+    pub fn subcommand(mut self, subcommand: CommandTree) -> CommandTree {
+        self.subcommands.push(subcommand);
+        self
+    }
+
+    /// Add a flag without value (e.g. "--verbose").
+    pub fn flag(mut self, flag: &str) -> CommandTree {
+        self.flags.push((flag.to_string(), None));
+        self
+    }
+
+    /// Add a flag followed by a value (e.g. "--format json").
+    pub fn flag_with_value(mut self, flag: &str, value: Arg) -> CommandTree {
+        self.flags.push((flag.to_string(), Some(value)));
+        self
+    }
+
+    /// Add a positional argument, after the previous ones.
+    pub fn arg(mut self, arg: Arg) -> CommandTree {
+        self.args.push(arg);
+        self
+    }
+
+    /// Set how the subcommands, flags and values are matched (only for the root command).
+    ///
+    /// The default is `Matcher::default()`, prefix matching which ignores case if `completion-ignore-case` is on.
+    pub fn matcher(mut self, matcher: Matcher) -> CommandTree {
+        self.matcher = Some(matcher);
+        self
+    }
+
+    /// Return the candidates for `text` given the `words` preceding it.
+    fn complete_words(&self, words: &[&str], text: &str) -> Vec<String> {
+        let matcher = self.matcher.unwrap_or_default();
+        let mut node = self;
+        let mut arg_index = 0;
+        let mut flag_value: Option<&Arg> = None;
+        for word in words {
+            if flag_value.take().is_some() {
+                continue;
+            }
+            if let Some((_, value)) = node.flags.iter().find(|(flag, _)| flag == word) {
+                flag_value = value.as_ref();
+            } else if word.starts_with('-') {
+                // unknown flag
+            } else if let Some(subcommand) = node.subcommands.iter().find(|c| c.name == *word).filter(|_| arg_index == 0) {
+                node = subcommand;
+            } else {
+                arg_index += 1;
+            }
+        }
+
+        if let Some(value) = flag_value {
+            return value.complete(text, matcher);
+        }
+        if text.starts_with('-') {
+            return matcher.filter(text, node.flags.iter().map(|(flag, _)| flag.clone()));
+        }
+        let mut candidates = Vec::new();
+        if arg_index == 0 {
+            candidates.extend(matcher.filter(text, node.subcommands.iter().map(|c| c.name.clone())));
+        }
+        if let Some(arg) = node.args.get(arg_index) {
+            candidates.extend(arg.complete(text, matcher));
+        }
+        candidates
+    }
+}
+
+impl Completer for CommandTree {
+    fn complete(&self, context: &CompletionContext) -> Vec<String> {
+        let words: Vec<&str> = context.words.iter().map(|w| w.as_str()).collect();
+        self.complete_words(&words, &context.word)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Arg, CommandTree};
+    use super::super::matcher::Matcher;
+
+    fn tree() -> CommandTree {
+        CommandTree::new("")
+            .matcher(Matcher::Prefix)
+            .subcommand(CommandTree::new("db")
+                .subcommand(CommandTree::new("connect")
+                    .flag("--ssl")
+                    .flag_with_value("--user", Arg::values(&["admin", "guest"]))
+                    .arg(Arg::Dynamic(Box::new(|_| vec!["localhost".to_string(), "remote".to_string()]))))
+                .subcommand(CommandTree::new("list")))
+            .subcommand(CommandTree::new("set")
+                .arg(Arg::values(&["editor", "pager"]))
+                .arg(Arg::values(&["vi", "less"])))
+    }
+
+    #[test]
+    fn subcommands() {
+        let tree = tree();
+        assert_eq!(tree.complete_words(&[], ""), vec!["db", "set"]);
+        assert_eq!(tree.complete_words(&[], "d"), vec!["db"]);
+        assert_eq!(tree.complete_words(&["db"], ""), vec!["connect", "list"]);
+        assert!(tree.complete_words(&["db", "list"], "").is_empty());
+    }
+
+    #[test]
+    fn args() {
+        let tree = tree();
+        assert_eq!(tree.complete_words(&["set"], "e"), vec!["editor"]);
+        assert_eq!(tree.complete_words(&["set", "pager"], ""), vec!["less", "vi"]);
+        assert!(tree.complete_words(&["set", "pager", "less"], "").is_empty());
+        assert_eq!(tree.complete_words(&["db", "connect"], "lo"), vec!["localhost"]);
+    }
+
+    #[test]
+    fn flags() {
+        let tree = tree();
+        assert_eq!(tree.complete_words(&["db", "connect"], "--"), vec!["--ssl", "--user"]);
+        assert_eq!(tree.complete_words(&["db", "connect", "--user"], ""), vec!["admin", "guest"]);
+        assert_eq!(tree.complete_words(&["db", "connect", "--user", "admin", "--ssl"], "r"), vec!["remote"]);
+    }
+
+    #[test]
+    fn fuzzy() {
+        let tree = tree().matcher(Matcher::Fuzzy);
+        assert_eq!(tree.complete_words(&["db"], "ct"), vec!["connect"]);
+        assert_eq!(tree.complete_words(&["set"], "pgr"), vec!["pager"]);
+    }
+}
@@ -0,0 +1,256 @@
+//! Completion with Rust functions instead of `extern` callbacks.
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::ptr;
+use libc::{c_char, c_int};
+
+use super::display;
+use super::ffi;
+use super::match_list::MatchList;
+use super::matcher;
+
+/// Where the completion is attempted.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompletionContext {
+    /// The whole line being edited.
+    pub line: String,
+    /// The position of the cursor, a byte offset in `line`.
+    pub point: usize,
+    /// The byte offset in `line` of the start of the word being completed.
+    pub start: usize,
+    /// The byte offset in `line` of the end of the word being completed.
+    pub end: usize,
+    /// The word being completed.
+    pub word: String,
+    /// The words preceding the word being completed, without their quotes and backslashes.
+    pub words: Vec<String>,
+    /// The index of the word being completed among the arguments (`words.len()`).
+    pub arg_index: usize,
+}
+
+impl CompletionContext {
+    /// Create the context of the completion of the word between the byte offsets `start` and `end` of `line`.
+    ///
+    /// Words are split on unquoted whitespaces, readline's `rl_char_is_quoted_p` deciding whether a character is quoted.
+    pub fn new(line: &str, start: usize, end: usize) -> CompletionContext {
+        let quote_characters = super::rl_completer_quote_characters().unwrap_or_else(|| "\"'".to_string());
+        let words = unsafe {
+            match ffi::rl_char_is_quoted_p {
+                Some(is_quoted) => {
+                    let c_line = CString::new(line).unwrap().into_raw();
+                    let words = split_words(&line[..start], &quote_characters, |i| is_quoted(c_line, i as c_int) != 0);
+                    drop(CString::from_raw(c_line));
+                    words
+                }
+                None => split_words(&line[..start], &quote_characters, |_| false),
+            }
+        };
+        CompletionContext {
+            line: line.to_string(),
+            point: end,
+            start,
+            end,
+            word: line[start..end].to_string(),
+            arg_index: words.len(),
+            words,
+        }
+    }
+}
+
+/// Split `line` on whitespaces not enclosed in `quote_characters`, nor escaped, nor `is_quoted`.
+fn split_words<Q>(line: &str, quote_characters: &str, is_quoted: Q) -> Vec<String> where Q: Fn(usize) -> bool {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        if escaped {
+            escaped = false;
+            word.get_or_insert_with(String::new).push(c);
+            continue;
+        }
+        match quote {
+            Some(q) if c == q => quote = None,
+            // no escape between single quotes
+            Some(q) if c == '\\' && q != '\'' => escaped = true,
+            Some(_) => word.get_or_insert_with(String::new).push(c),
+            None if c == '\\' => {
+                escaped = true;
+                word.get_or_insert_with(String::new);
+            }
+            None if quote_characters.contains(c) => {
+                quote = Some(c);
+                word.get_or_insert_with(String::new);
+            }
+            None if c.is_whitespace() && !is_quoted(i) => words.extend(word.take()),
+            None => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    words
+}
+
+/// Give the candidates completing a word.
+pub trait Completer {
+    /// Return the candidates for the word being completed in `context`.
+    fn complete(&self, context: &CompletionContext) -> Vec<String>;
+}
+
+impl<F> Completer for F where F: Fn(&CompletionContext) -> Vec<String> {
+    fn complete(&self, context: &CompletionContext) -> Vec<String> {
+        self(context)
+    }
+}
+
+/// The candidates of all the completers, in order.
+impl Completer for Vec<Box<dyn Completer>> {
+    fn complete(&self, context: &CompletionContext) -> Vec<String> {
+        self.iter().flat_map(|completer| completer.complete(context)).collect()
+    }
+}
+
+thread_local! {
+    static COMPLETER: RefCell<Option<Box<dyn Completer>>> = const { RefCell::new(None) };
+}
+
+/// Return the context of the completion of `text` from readline's line and byte offsets in it,
+/// both converted the way the line is displayed when they are not valid UTF-8.
+fn raw_context(line: &[u8], text: &[u8], start: usize, end: usize, point: usize) -> CompletionContext {
+    let lossy = String::from_utf8_lossy(line);
+    let offset = |offset| display::lossy_offset(line, &lossy, offset);
+    let mut context = CompletionContext::new(&lossy, offset(start), offset(end));
+    context.point = offset(point);
+    // readline may have dequoted the word
+    context.word = String::from_utf8_lossy(text).into_owned();
+    context
+}
+
+extern "C" fn attempted_completion(text: *const c_char, start: c_int, end: c_int) -> *mut *const c_char {
+    // readline's default (filename) completion is only done through `FilenameCompleter`
+    super::rl_attempted_completion_over(true);
+    let c_text = unsafe { CStr::from_ptr(text).to_bytes() };
+    let point = unsafe { ffi::rl_point as usize };
+    let context = raw_context(&display::line_bytes(), c_text, start as usize, end as usize, point);
+    let entries = COMPLETER.with(|c| match *c.borrow() {
+        Some(ref c) => c.complete(&context),
+        None => Vec::new(),
+    });
+    // not readline's common prefix, which would replace the text by an unrelated one with substring or fuzzy matches
+    let prefix = matcher::common_prefix(&context.word, &entries, matcher::completion_ignore_case());
+    // freed by readline
+    MatchList::new(&prefix, entries).into_raw() as *mut *const c_char
+}
+
+/// Set the `Completer` used when TAB is pressed.
+///
+/// It replaces the attempted completion function; `None` restores readline's default completion.
+pub fn set_completer(completer: Option<Box<dyn Completer>>) {
+    let installed = completer.is_some();
+    COMPLETER.with(|c| *c.borrow_mut() = completer);
+    unsafe { ffi::rl_attempted_completion_function = if installed { Some(attempted_completion) } else { None } }
+}
+
+/// A hook displaying the completion matches, given the length of the longest one.
+pub type DisplayMatchesHook = Box<dyn FnMut(&[String], usize)>;
+
+thread_local! {
+    static DISPLAY_MATCHES_HOOK: RefCell<Option<DisplayMatchesHook>> = const { RefCell::new(None) };
+}
+
+/// Return the `num_matches` matches following the common prefix in `matches`, invalid UTF-8 replaced.
+fn match_strings(matches: *mut *mut c_char, num_matches: c_int) -> Vec<String> {
+    // matches[0] is the common prefix of the matches
+    (1..num_matches as isize + 1)
+        .map(|i| super::c_str_to_string(unsafe { *matches.offset(i) }).unwrap_or_default())
+        .collect()
+}
+
+extern "C" fn display_matches_hook(matches: *mut *mut c_char, num_matches: c_int, max_length: c_int) {
+    let matches = match_strings(matches, num_matches);
+    DISPLAY_MATCHES_HOOK.with(|h| {
+        if let Some(ref mut h) = *h.borrow_mut() {
+            h(&matches, max_length as usize);
+        }
+    });
+    // the prompt and the line are displayed again below the matches
+    display::reset();
+    unsafe { ffi::rl_forced_update_display() };
+}
+
+/// Set the function displaying the completion matches instead of readline, for example to add descriptions or colors.
+///
+/// The hook should start by moving to a new line (as `display_match_list` does);
+/// the prompt and the line being edited are then displayed again below its output.
+/// (See [rl_completion_display_matches_hook](http://cnswww.cns.cwru.edu/php/chet/readline/readline.html))
+pub fn set_rl_completion_display_matches_hook(hook: Option<DisplayMatchesHook>) {
+    let installed = hook.is_some();
+    DISPLAY_MATCHES_HOOK.with(|h| *h.borrow_mut() = hook);
+    unsafe { ffi::rl_completion_display_matches_hook = if installed { Some(display_matches_hook) } else { None } }
+}
+
+/// Display `matches` in columns, the way readline lists completions.
+///
+/// (See [rl_display_match_list](http://cnswww.cns.cwru.edu/php/chet/readline/readline.html))
+pub fn display_match_list(matches: &[String]) {
+    if matches.is_empty() {
+        return;
+    }
+    let c_matches: Vec<CString> = matches.iter().map(|m| CString::new(m.as_str()).unwrap()).collect();
+    let prefix = CString::new("").unwrap();
+    let mut c_ptrs: Vec<*mut c_char> = Vec::with_capacity(matches.len() + 2);
+    c_ptrs.push(prefix.as_ptr() as *mut c_char);
+    c_ptrs.extend(c_matches.iter().map(|m| m.as_ptr() as *mut c_char));
+    c_ptrs.push(ptr::null_mut());
+    let max = matches.iter().map(|m| display::width(m)).max().unwrap_or(0);
+    unsafe { ffi::rl_display_match_list(c_ptrs.as_mut_ptr(), matches.len() as c_int, max as c_int) };
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn split_words() {
+        let words = super::split_words("cp 'my file' other\\ file \"a \\\" b\" ", "\"'", |_| false);
+        assert_eq!(words, vec!["cp", "my file", "other file", "a \" b"]);
+        let words = super::split_words("echo a b", "\"'", |i| i == 6);
+        assert_eq!(words, vec!["echo", "a b"]);
+    }
+
+    #[test]
+    fn completion_context() {
+        let context = super::CompletionContext::new("db connect 'my host' lo", 21, 23);
+        assert_eq!(context.word, "lo");
+        assert_eq!(context.words, vec!["db", "connect", "my host"]);
+        assert_eq!(context.arg_index, 3);
+        assert_eq!(context.point, 23);
+    }
+
+    #[test]
+    fn raw_context() {
+        // a multibyte character before the word
+        let context = super::raw_context("café lo".as_bytes(), b"lo", 6, 8, 8);
+        assert_eq!((context.start, context.end, context.point), (6, 8, 8));
+        assert_eq!(context.word, "lo");
+        assert_eq!(context.words, vec!["café"]);
+
+        // an invalid byte, replaced by a 3-byte character
+        let context = super::raw_context(b"caf\xe9 lo", b"lo", 5, 7, 6);
+        assert_eq!(context.line, "caf\u{fffd} lo");
+        assert_eq!((context.start, context.end, context.point), (7, 9, 8));
+        assert_eq!(context.word, "lo");
+        assert_eq!(context.words, vec!["caf\u{fffd}"]);
+
+        // the end inside a character, and an invalid word
+        let context = super::raw_context("ls é".as_bytes(), b"\xc3", 3, 4, 4);
+        assert_eq!((context.start, context.end, context.point), (3, 3, 3));
+        assert_eq!(context.word, "\u{fffd}");
+    }
+
+    #[test]
+    fn match_strings() {
+        // filenames of another encoding
+        let mut matches = [c"caf".as_ptr(), c"caf\xe9".as_ptr(), c"cafe".as_ptr(), std::ptr::null()];
+        let matches = super::match_strings(matches.as_mut_ptr() as *mut *mut libc::c_char, 2);
+        assert_eq!(matches, vec!["caf\u{fffd}", "cafe"]);
+    }
+}
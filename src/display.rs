@@ -0,0 +1,299 @@
+//! Drawing the prompt and the line buffer for custom redisplay functions.
+//!
+//! Escape sequences and characters enclosed in `RL_PROMPT_START_IGNORE`/`RL_PROMPT_END_IGNORE`
+//! have no width, wide characters take two columns and the cursor is put back at the point.
+
+use std::cell::Cell;
+use std::ffi::{CStr, CString};
+use unicode_width::UnicodeWidthChar;
+
+use super::ffi;
+use super::highlight;
+use super::hint;
+
+/// Character marking the beginning of invisible characters in a prompt (`\001`).
+pub const RL_PROMPT_START_IGNORE: char = '\x01';
+/// Character marking the end of invisible characters in a prompt (`\002`).
+pub const RL_PROMPT_END_IGNORE: char = '\x02';
+
+thread_local! {
+    // row of the cursor relative to the first row of the prompt
+    static CURSOR_ROW: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Forget where the previous line was drawn, the cursor is at the start of a new line.
+pub fn reset() {
+    CURSOR_ROW.with(|row| row.set(0));
+}
+
+/// Erase the prompt and the line drawn last, leaving the cursor at the start of the prompt.
+#[cfg(feature = "pure-rust")]
+pub fn clear() {
+    let row = CURSOR_ROW.with(|row| row.replace(0));
+    let up = if row > 0 { format!("\x1b[{}A", row) } else { String::new() };
+    write_out(&format!("{}\r\x1b[J", up));
+}
+
+/// Return the current content of `rl_line_buffer` and the point as a byte offset in it.
+pub fn line_buffer() -> (String, usize) {
+    lossy_line(&line_bytes(), unsafe { ffi::rl_point as usize })
+}
+
+/// Return the bytes of `rl_line_buffer`, up to `rl_end`.
+pub fn line_bytes() -> Vec<u8> {
+    unsafe {
+        if ffi::rl_line_buffer.is_null() {
+            return Vec::new();
+        }
+        let line = CStr::from_ptr(ffi::rl_line_buffer).to_bytes();
+        line[..line.len().min(ffi::rl_end as usize)].to_vec()
+    }
+}
+
+/// Return `line` as a string, invalid sequences replaced, and `point` as a byte offset in it.
+fn lossy_line(line: &[u8], point: usize) -> (String, usize) {
+    let lossy = String::from_utf8_lossy(line).into_owned();
+    let point = lossy_offset(line, &lossy, point);
+    (lossy, point)
+}
+
+/// Return the byte `offset` of `line` as a byte offset in `lossy`, `line` with invalid sequences replaced.
+pub fn lossy_offset(line: &[u8], lossy: &str, offset: usize) -> usize {
+    // In the C locale, readline moves the point byte by byte: it may be in the middle of a character,
+    // which is then displayed after the cursor.
+    let mut offset = offset.min(line.len());
+    while offset > 0 && offset < line.len() && line[offset] & 0xc0 == 0x80 {
+        offset -= 1;
+    }
+    let mut before = String::from_utf8_lossy(&line[..offset]).len().min(lossy.len());
+    while !lossy.is_char_boundary(before) {
+        before -= 1;
+    }
+    before
+}
+
+/// Return the prompt readline is currently displaying.
+pub fn display_prompt() -> String {
+    unsafe {
+        if ffi::rl_display_prompt.is_null() {
+            String::new()
+        } else {
+            String::from_utf8_lossy(CStr::from_ptr(ffi::rl_display_prompt).to_bytes()).into_owned()
+        }
+    }
+}
+
+/// Write `s` to `rl_outstream`.
+pub fn write_out(s: &str) {
+    let c_s = CString::new(s.replace('\0', "")).unwrap();
+    unsafe {
+        libc::fputs(c_s.as_ptr(), ffi::rl_outstream);
+        libc::fflush(ffi::rl_outstream);
+    }
+}
+
+fn screen_width() -> usize {
+    let (mut rows, mut cols) = (0, 0);
+    unsafe { ffi::rl_get_screen_size(&mut rows, &mut cols) };
+    if cols <= 0 { 80 } else { cols as usize }
+}
+
+/// Remove `RL_PROMPT_START_IGNORE` and `RL_PROMPT_END_IGNORE` markers, keeping what they enclose.
+pub fn strip_ignore_markers(s: &str) -> String {
+    s.chars().filter(|&c| c != RL_PROMPT_START_IGNORE && c != RL_PROMPT_END_IGNORE).collect()
+}
+
+/// Return the length of the escape sequence at the start of `s`, which starts with ESC.
+pub fn escape_len(s: &str) -> usize {
+    let mut chars = s.char_indices().skip(1);
+    match chars.next() {
+        // CSI: parameters then a final byte in '@'..='~'
+        Some((_, '[')) => chars.find(|&(_, c)| ('@'..='~').contains(&c)).map_or(s.len(), |(i, _)| i + 1),
+        // OSC: terminated by BEL or ST (ESC \)
+        Some((_, ']')) => {
+            while let Some((i, c)) = chars.next() {
+                match c {
+                    '\x07' => return i + 1,
+                    '\x1b' => return chars.next().map_or(s.len(), |(i, c)| i + c.len_utf8()),
+                    _ => {}
+                }
+            }
+            s.len()
+        }
+        Some((i, c)) => i + c.len_utf8(),
+        None => s.len(),
+    }
+}
+
+/// Return the length of the invisible characters enclosed in `RL_PROMPT_START_IGNORE`/`RL_PROMPT_END_IGNORE` at the start of `s`.
+fn ignored_len(s: &str) -> usize {
+    s.find(RL_PROMPT_END_IGNORE).map_or(s.len(), |i| i + 1)
+}
+
+/// Visible characters of `s` with their width.
+fn visible_chars<'a>(s: &'a str) -> impl Iterator<Item = (char, usize)> + 'a {
+    let mut rest = s;
+    std::iter::from_fn(move || {
+        loop {
+            let c = rest.chars().next()?;
+            let len = match c {
+                RL_PROMPT_START_IGNORE => ignored_len(rest),
+                '\x1b' => escape_len(rest),
+                RL_PROMPT_END_IGNORE => 1,
+                c => {
+                    rest = &rest[c.len_utf8()..];
+                    return Some((c, c.width().unwrap_or(0)));
+                }
+            };
+            rest = &rest[len..];
+        }
+    })
+}
+
+/// Enclose the escape sequences of `s` in `RL_PROMPT_START_IGNORE`/`RL_PROMPT_END_IGNORE`,
+/// leaving those already enclosed unchanged.
+pub fn ignore_escape_sequences(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(i) = rest.find(['\x1b', RL_PROMPT_START_IGNORE]) {
+        out.push_str(&rest[..i]);
+        rest = &rest[i..];
+        let len = if rest.starts_with(RL_PROMPT_START_IGNORE) {
+            let len = ignored_len(rest);
+            out.push_str(&rest[..len]);
+            len
+        } else {
+            let len = escape_len(rest);
+            out.push(RL_PROMPT_START_IGNORE);
+            out.push_str(&rest[..len]);
+            out.push(RL_PROMPT_END_IGNORE);
+            len
+        };
+        rest = &rest[len..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Return the characters of `s` that are displayed, without escape sequences and ignored characters.
+pub fn visible(s: &str) -> String {
+    visible_chars(s).map(|(c, _)| c).collect()
+}
+
+/// Return the number of columns needed to display `s`.
+pub fn width(s: &str) -> usize {
+    visible_chars(s).map(|(_, w)| w).sum()
+}
+
+/// Position (row, column) reached after displaying `s` from `pos` on a screen `cols` wide.
+fn advance(pos: (usize, usize), s: &str, cols: usize) -> (usize, usize) {
+    visible_chars(s).fold(pos, |(row, col), (_, w)| {
+        // a wide character which does not fit is wrapped to the next row
+        let (row, col) = if col + w > cols { (row + 1, 0) } else { (row, col) };
+        if col + w == cols { (row + 1, 0) } else { (row, col + w) }
+    })
+}
+
+/// Draw `prompt` followed by `styled` (the line buffer with escape sequences, and anything displayed after it)
+/// and move the cursor after `before_point` (the part of the line buffer before the point, without escape sequences).
+pub fn draw(prompt: &str, styled: &str, before_point: &str) {
+    let cols = screen_width();
+    let mut out = String::new();
+    let row = CURSOR_ROW.with(|row| row.get());
+    if row > 0 {
+        out.push_str(&format!("\x1b[{}A", row));
+    }
+    out.push_str("\r\x1b[J");
+    out.push_str(&strip_ignore_markers(prompt));
+    out.push_str(styled);
+
+    let start = advance((0, 0), prompt, cols);
+    let end = advance(start, styled, cols);
+    if end.1 == 0 && end != start {
+        // the terminal does not wrap until the next character is displayed
+        out.push_str(" \r");
+    }
+    let cursor = if unsafe { ffi::rl_done } != 0 {
+        // readline outputs a newline when the line is accepted
+        end
+    } else {
+        advance(start, before_point, cols)
+    };
+    if end.0 > cursor.0 {
+        out.push_str(&format!("\x1b[{}A", end.0 - cursor.0));
+    }
+    out.push('\r');
+    if cursor.1 > 0 {
+        out.push_str(&format!("\x1b[{}C", cursor.1));
+    }
+    CURSOR_ROW.with(|row| row.set(cursor.0));
+    write_out(&out);
+}
+
+extern "C" fn redisplay() {
+    let (line, point) = line_buffer();
+    let mut styled = highlight::highlight(&line, point);
+    if unsafe { ffi::rl_done } == 0 {
+        if let Some(hint) = hint::hint(&line, point) {
+            styled.push_str(&highlight::highlight_hint(&hint));
+        }
+    }
+    draw(&display_prompt(), &styled, &line[..point]);
+}
+
+/// Replace readline's redisplay function when a `Highlighter` or a `Hinter` is set, restore it otherwise.
+pub fn update_redisplay_function() {
+    let custom = highlight::has_highlighter() || hint::has_hinter();
+    unsafe {
+        ffi::rl_redisplay_function = if custom {
+            Some(redisplay)
+        } else {
+            Some(ffi::rl_redisplay)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn width() {
+        assert_eq!(super::width("abc"), 3);
+        assert_eq!(super::width("\x1b[1;31mabc\x1b[0m"), 3);
+        assert_eq!(super::width("\x01\x1b[32m\x02> \x01\x1b[0m\x02"), 2);
+        assert_eq!(super::width("\x1b]0;title\x07$ "), 2);
+        assert_eq!(super::width("日本"), 4);
+        assert_eq!(super::width("e\u{301}"), 1);
+    }
+
+    #[test]
+    fn lossy_line() {
+        assert_eq!(super::lossy_line("aé".as_bytes(), 3), ("aé".to_string(), 3));
+        // the point inside "é"
+        assert_eq!(super::lossy_line("é".as_bytes(), 1), ("é".to_string(), 0));
+        assert_eq!(super::lossy_line("éa".as_bytes(), 1), ("éa".to_string(), 0));
+        assert_eq!(super::lossy_line(b"a\xffb", 2), ("a\u{fffd}b".to_string(), 4));
+        assert_eq!(super::lossy_line(b"ab", 5), ("ab".to_string(), 2));
+    }
+
+    #[test]
+    fn advance() {
+        assert_eq!(super::advance((0, 0), "abcd", 10), (0, 4));
+        assert_eq!(super::advance((0, 8), "abcd", 10), (1, 2));
+        assert_eq!(super::advance((0, 6), "abcd", 10), (1, 0));
+        // the wide character does not fit on the first row
+        assert_eq!(super::advance((0, 9), "日", 10), (1, 2));
+    }
+
+    #[test]
+    fn ignore_escape_sequences() {
+        assert_eq!(super::ignore_escape_sequences("\x1b[32m> \x1b[0m"), "\x01\x1b[32m\x02> \x01\x1b[0m\x02");
+        assert_eq!(super::ignore_escape_sequences("\x01\x1b[32m\x02> "), "\x01\x1b[32m\x02> ");
+        assert_eq!(super::ignore_escape_sequences("\x1b]0;title\x1b\\$ "), "\x01\x1b]0;title\x1b\\\x02$ ");
+        assert_eq!(super::ignore_escape_sequences("> "), "> ");
+    }
+
+    #[test]
+    fn strip_ignore_markers() {
+        assert_eq!(super::strip_ignore_markers("\x01\x1b[32m\x02> \x01\x1b[0m\x02"), "\x1b[32m> \x1b[0m");
+    }
+}
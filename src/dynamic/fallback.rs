@@ -0,0 +1,346 @@
+//! What the functions do when the library, or the function, is missing.
+//!
+//! Lines are read from stdin without editing, there is no history, no completion and no key binding:
+//! functions report a failure when they can.
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::io::{self, Write};
+use std::ptr;
+use libc::{c_char, c_int, c_void, FILE};
+
+use super::super::display;
+use super::super::ffi::{CommandFunction, HistEntry, Keymap};
+use super::super::{CompletionEntryFunction, VCPFunction};
+use super::{rl_done, rl_end, rl_getc_function, rl_instream, rl_line_buffer, rl_outstream, rl_point};
+
+struct State {
+    prompt: String,
+    handler: VCPFunction,
+    // line read by the callback interface
+    line: Vec<u8>,
+    keyboard_input_timeout: c_int,
+    screen_size: (c_int, c_int),
+}
+
+thread_local! {
+    static STATE: RefCell<State> = const { RefCell::new(State {
+        prompt: String::new(),
+        handler: None,
+        line: Vec::new(),
+        keyboard_input_timeout: 100_000,
+        screen_size: (24, 80),
+    }) };
+}
+
+/// Read a byte from `fd`, `None` at end of file.
+fn read_byte(fd: c_int) -> Option<u8> {
+    let mut byte = 0u8;
+    loop {
+        match unsafe { libc::read(fd, &mut byte as *mut u8 as *mut c_void, 1) } {
+            1 => return Some(byte),
+            n if n < 0 && io::Error::last_os_error().kind() == io::ErrorKind::Interrupted => {}
+            _ => return None,
+        }
+    }
+}
+
+/// Read a byte with `rl_getc_function`, from `rl_instream` if it is not set.
+fn getc() -> Option<u8> {
+    match unsafe { rl_getc_function } {
+        Some(getc) => match unsafe { getc(rl_instream) } {
+            libc::EOF => None,
+            c => Some(c as u8),
+        },
+        None => read_byte(input_fd()),
+    }
+}
+
+/// Read a line with `read`, without its newline. Return `None` at end of file.
+fn read_line<F>(mut read: F) -> Option<Vec<u8>> where F: FnMut() -> Option<u8> {
+    let mut line = Vec::new();
+    loop {
+        match read() {
+            Some(b'\n') => return Some(line),
+            Some(c) => line.push(c),
+            None if line.is_empty() => return None,
+            None => return Some(line),
+        }
+    }
+}
+
+/// The file descriptor of `rl_instream`, stdin if it is not set.
+fn input_fd() -> c_int {
+    unsafe { if rl_instream.is_null() { 0 } else { libc::fileno(rl_instream) } }
+}
+
+/// Write the prompt to `rl_outstream`, or stdout if it is not set.
+fn show_prompt(prompt: &str) {
+    if unsafe { rl_outstream.is_null() } {
+        let mut stdout = io::stdout();
+        let _ = stdout.write_all(display::strip_ignore_markers(prompt).as_bytes());
+        let _ = stdout.flush();
+    } else {
+        display::write_out(&display::strip_ignore_markers(prompt));
+    }
+}
+
+/// Make `line` the line buffer and return a `malloc`'d copy of it.
+unsafe fn accept(line: &[u8]) -> *mut c_char {
+    let c_line = CString::new(line.split(|&b| b == 0).next().unwrap_or_default()).unwrap();
+    libc::free(rl_line_buffer as *mut c_void);
+    rl_line_buffer = libc::strdup(c_line.as_ptr());
+    rl_end = c_line.as_bytes().len() as c_int;
+    rl_point = rl_end;
+    rl_done = 1;
+    libc::strdup(c_line.as_ptr())
+}
+
+fn prompt_string(prompt: *const c_char) -> String {
+    if prompt.is_null() {
+        String::new()
+    } else {
+        unsafe { CStr::from_ptr(prompt) }.to_string_lossy().into_owned()
+    }
+}
+
+/// The prompt is written to `rl_outstream` and the line read from `rl_instream`.
+pub unsafe fn readline(prompt: *const c_char) -> *const c_char {
+    show_prompt(&prompt_string(prompt));
+    match read_line(getc) {
+        Some(line) => accept(&line),
+        None => ptr::null(),
+    }
+}
+
+pub unsafe fn rl_callback_handler_install(prompt: *const c_char, handler: VCPFunction) {
+    let prompt = prompt_string(prompt);
+    show_prompt(&prompt);
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        state.prompt = prompt;
+        state.handler = handler;
+        state.line.clear();
+    });
+}
+
+/// Read a byte, calling the handler at the end of a line.
+pub unsafe fn rl_callback_read_char() {
+    let c = getc();
+    let line = STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        match c {
+            Some(b'\n') => Some(Some(std::mem::take(&mut state.line))),
+            Some(c) => {
+                state.line.push(c);
+                None
+            }
+            None => Some(None),
+        }
+    });
+    let handler = STATE.with(|state| state.borrow().handler);
+    if let (Some(line), Some(handler)) = (line, handler) {
+        handler(line.map_or(ptr::null_mut(), |line| accept(&line)));
+        // unless the handler removed itself
+        if let Some(prompt) = STATE.with(|state| state.borrow().handler.map(|_| state.borrow().prompt.clone())) {
+            show_prompt(&prompt);
+        }
+    }
+}
+
+pub unsafe fn rl_getc(stream: *mut FILE) -> c_int {
+    read_byte(if stream.is_null() { 0 } else { libc::fileno(stream) }).map_or(libc::EOF, c_int::from)
+}
+
+pub unsafe fn rl_callback_handler_remove() {
+    STATE.with(|state| state.borrow_mut().handler = None);
+}
+
+pub unsafe fn rl_set_prompt(prompt: *const c_char) -> c_int {
+    let prompt = prompt_string(prompt);
+    STATE.with(|state| state.borrow_mut().prompt = prompt);
+    0
+}
+
+pub unsafe fn rl_set_keyboard_input_timeout(usecs: c_int) -> c_int {
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let previous = state.keyboard_input_timeout;
+        if usecs >= 0 {
+            state.keyboard_input_timeout = usecs;
+        }
+        previous
+    })
+}
+
+/// The line is accepted.
+pub unsafe fn rl_newline(_count: c_int, _key: c_int) -> c_int {
+    rl_done = 1;
+    0
+}
+
+/// Nothing is displayed but the prompt: these do nothing.
+pub unsafe fn rl_initialize() -> c_int {
+    0
+}
+
+pub unsafe fn rl_redisplay() {}
+
+pub unsafe fn rl_forced_update_display() -> c_int {
+    0
+}
+
+pub unsafe fn rl_clear_visible_line() -> c_int {
+    0
+}
+
+pub unsafe fn rl_ding() -> c_int {
+    0
+}
+
+pub unsafe fn rl_display_match_list(_matches: *mut *mut c_char, _len: c_int, _max: c_int) {}
+
+pub unsafe fn rl_get_screen_size(rows: *mut c_int, cols: *mut c_int) {
+    (*rows, *cols) = STATE.with(|state| state.borrow().screen_size);
+}
+
+pub unsafe fn rl_set_screen_size(rows: c_int, cols: c_int) {
+    STATE.with(|state| state.borrow_mut().screen_size = (rows, cols));
+}
+
+/// The terminal is never prepared and no signal is caught: these do nothing.
+pub unsafe fn rl_resize_terminal() {}
+
+pub unsafe fn rl_cleanup_after_signal() {}
+
+pub unsafe fn rl_reset_after_signal() {}
+
+pub unsafe fn rl_free_line_state() {}
+
+pub unsafe fn rl_free_undo_list() {}
+
+/// The line cannot be edited: nothing is inserted.
+pub unsafe fn rl_insert_text(_text: *const c_char) -> c_int {
+    0
+}
+
+pub unsafe fn rl_forward_char(_count: c_int, _key: c_int) -> c_int {
+    0
+}
+
+/// There are no init file, variables or key bindings: these fail.
+pub unsafe fn rl_read_init_file(_filename: *const c_char) -> c_int {
+    libc::ENOSYS
+}
+
+pub unsafe fn rl_parse_and_bind(_line: *const c_char) -> c_int {
+    libc::ENOSYS
+}
+
+pub unsafe fn rl_variable_bind(_variable: *const c_char, _value: *const c_char) -> c_int {
+    -1
+}
+
+pub unsafe fn rl_variable_value(_variable: *const c_char) -> *const c_char {
+    ptr::null()
+}
+
+pub unsafe fn rl_add_defun(_name: *const c_char, _function: Option<CommandFunction>, _key: c_int) -> c_int {
+    -1
+}
+
+pub unsafe fn rl_bind_key(_key: c_int, _function: Option<CommandFunction>) -> c_int {
+    -1
+}
+
+pub unsafe fn rl_bind_keyseq(_keyseq: *const c_char, _function: Option<CommandFunction>) -> c_int {
+    -1
+}
+
+pub unsafe fn rl_function_of_keyseq(_keyseq: *const c_char, _map: Keymap, _typ: *mut c_int) -> Option<CommandFunction> {
+    None
+}
+
+/// There is no completion: no match.
+pub unsafe fn rl_completion_matches(_text: *const c_char, _entry_func: CompletionEntryFunction) -> *mut *const c_char {
+    ptr::null_mut()
+}
+
+pub unsafe fn rl_filename_completion_function(_text: *const c_char, _state: c_int) -> *const c_char {
+    ptr::null()
+}
+
+/// There is no history: nothing is kept and the files cannot be read or written.
+pub unsafe fn using_history() {}
+
+pub unsafe fn add_history(_line: *const c_char) {}
+
+pub unsafe fn history_get(_offset: c_int) -> *mut HistEntry {
+    ptr::null_mut()
+}
+
+pub unsafe fn clear_history() {}
+
+pub unsafe fn where_history() -> c_int {
+    0
+}
+
+pub unsafe fn history_set_pos(_pos: c_int) -> c_int {
+    0
+}
+
+pub unsafe fn current_history() -> *mut HistEntry {
+    ptr::null_mut()
+}
+
+pub unsafe fn history_search_prefix(_string: *const c_char, _direction: c_int) -> c_int {
+    -1
+}
+
+pub unsafe fn stifle_history(_max: c_int) {}
+
+pub unsafe fn unstifle_history() -> c_int {
+    0
+}
+
+pub unsafe fn history_is_stifled() -> c_int {
+    0
+}
+
+pub unsafe fn read_history(_filename: *const c_char) -> c_int {
+    libc::ENOSYS
+}
+
+pub unsafe fn write_history(_filename: *const c_char) -> c_int {
+    libc::ENOSYS
+}
+
+pub unsafe fn append_history(_nelements: c_int, _filename: *const c_char) -> c_int {
+    libc::ENOSYS
+}
+
+pub unsafe fn history_truncate_file(_filename: *const c_char, _nlines: c_int) -> c_int {
+    libc::ENOSYS
+}
+
+#[cfg(test)]
+mod tests {
+    use libc::c_int;
+
+    #[test]
+    fn read_line() {
+        let mut fds = [0 as c_int; 2];
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        let input = b"first\n\nlast";
+        unsafe {
+            libc::write(fds[1], input.as_ptr() as *const libc::c_void, input.len());
+            libc::close(fds[1]);
+        }
+        let read = || super::read_byte(fds[0]);
+        assert_eq!(super::read_line(read), Some(b"first".to_vec()));
+        assert_eq!(super::read_line(read), Some(Vec::new()));
+        assert_eq!(super::read_line(read), Some(b"last".to_vec()));
+        assert_eq!(super::read_line(read), None);
+        unsafe { libc::close(fds[0]) };
+    }
+}
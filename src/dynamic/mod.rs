@@ -0,0 +1,483 @@
+//! The readline API resolved at runtime, for the `dynamic` feature.
+//!
+//! The first call loads libreadline (or libedit) with `dlopen`. The variables are Rust statics
+//! copied to the library before each call and read back after it, and around each function
+//! the library calls back, so that the rest of the crate uses them as if they were linked.
+//! When no library is found, or a function is missing from it, the function of `fallback` is used:
+//! lines are read from stdin without editing.
+
+#![allow(non_upper_case_globals)]
+
+use std::cell::Cell;
+use std::ffi::CStr;
+use std::mem;
+use std::ptr;
+use std::sync::{Mutex, OnceLock};
+use libc::{c_char, c_int, c_void, FILE};
+
+use super::ffi::{CommandFunction, DequoteFunction, DirectoryHookFunction, DisplayMatchesFunction, GetcFunction, HistEntry,
+                 HookFunction, Keymap, LineBufFunction, QuoteFunction, VoidFunction};
+use super::{CompleteLineFunction, CompletionEntryFunction, CompletionFunction, VCPFunction};
+
+mod fallback;
+
+// tried in order
+#[cfg(target_os = "macos")]
+const LIBRARIES: &[&CStr] = &[c"libreadline.8.dylib", c"libreadline.7.dylib", c"libedit.3.dylib"];
+#[cfg(not(target_os = "macos"))]
+const LIBRARIES: &[&CStr] = &[c"libreadline.so.8", c"libreadline.so.7", c"libedit.so.2", c"libedit.so.0"];
+
+unsafe fn symbol(handle: *mut c_void, name: &str) -> *mut c_void {
+    libc::dlsym(handle, name.as_ptr() as *const c_char)
+}
+
+/// Defines the data variables with readline's default values, and their addresses in the library.
+macro_rules! variables {
+    ($($name:ident: $t:ty = $default:expr;)*) => {
+        $(pub static mut $name: $t = $default;)*
+
+        struct Variables {
+            $($name: *mut $t,)*
+        }
+
+        #[derive(Clone, Copy)]
+        struct Values {
+            $($name: $t,)*
+        }
+
+        impl Variables {
+            unsafe fn resolve(handle: *mut c_void) -> Variables {
+                Variables { $($name: symbol(handle, concat!(stringify!($name), "\0")) as *mut $t,)* }
+            }
+
+            /// Take the library's values, except for the variables already set.
+            #[allow(clippy::cmp_null)]
+            unsafe fn init(&self) {
+                $(if !self.$name.is_null() {
+                    if $name == $default { $name = *self.$name } else { *self.$name = $name }
+                })*
+            }
+
+            unsafe fn push(&self) -> Values {
+                $(if !self.$name.is_null() {
+                    *self.$name = $name;
+                })*
+                Values { $($name,)* }
+            }
+
+            /// Read back the values changed by the library since `pushed`, or all of them.
+            unsafe fn pull(&self, pushed: Option<&Values>) {
+                $(if !self.$name.is_null() && pushed.is_none_or(|pushed| *self.$name != pushed.$name) {
+                    $name = *self.$name;
+                })*
+            }
+        }
+    };
+}
+
+/// Defines the function variables with their default value, and their addresses in the library.
+/// The library is given a trampoline calling the Rust function.
+macro_rules! hooks {
+    ($($name:ident: $t:ty = $default:expr, fn($($arg:ident: $arg_t:ty),*) $(-> $r:ty)?;)*) => {
+        $(pub static mut $name: Option<$t> = $default;
+
+        mod $name {
+            use super::*;
+
+            pub extern "C" fn trampoline($($arg: $arg_t),*) $(-> $r)? {
+                callback(|| unsafe { super::$name.expect("hook called after its removal")($($arg),*) })
+            }
+        })*
+
+        struct Hooks {
+            $($name: *mut usize,)*
+            // the library's own functions, given back as is
+            defaults: HookValues,
+        }
+
+        #[derive(Clone, Copy)]
+        struct HookValues {
+            $($name: usize,)*
+        }
+
+        impl Hooks {
+            unsafe fn resolve(handle: *mut c_void) -> Hooks {
+                Hooks {
+                    $($name: symbol(handle, concat!(stringify!($name), "\0")) as *mut usize,)*
+                    defaults: HookValues { $($name: 0,)* },
+                }
+            }
+
+            unsafe fn init(&mut self) {
+                $(if !self.$name.is_null() {
+                    self.defaults.$name = *self.$name;
+                    if address($name) == address::<$t>($default) {
+                        $name = mem::transmute::<usize, Option<$t>>(*self.$name);
+                    }
+                })*
+                self.push();
+            }
+
+            unsafe fn push(&self) -> HookValues {
+                let values = HookValues {
+                    $($name: match address($name) {
+                        0 => 0,
+                        f if f == self.defaults.$name => f,
+                        f if f == address::<$t>($default) && self.defaults.$name != 0 => self.defaults.$name,
+                        _ => $name::trampoline as *const () as usize,
+                    },)*
+                };
+                $(if !self.$name.is_null() {
+                    *self.$name = values.$name;
+                })*
+                values
+            }
+
+            unsafe fn pull(&self, pushed: Option<&HookValues>) {
+                $(if !self.$name.is_null() {
+                    let value = *self.$name;
+                    if value != $name::trampoline as *const () as usize && pushed.is_none_or(|pushed| value != pushed.$name) {
+                        $name = mem::transmute::<usize, Option<$t>>(value);
+                    }
+                })*
+            }
+        }
+    };
+}
+
+/// Defines the functions resolved in the library. Those of the first list are called as is,
+/// the others have a wrapper translating their arguments.
+macro_rules! functions {
+    ($(fn $name:ident($($arg:ident: $t:ty),*) $(-> $r:ty)?;)* custom: $(fn $cname:ident($($carg:ident: $ct:ty),*) $(-> $cr:ty)?;)*) => {
+        struct Functions {
+            $($name: Option<unsafe extern "C" fn($($t),*) $(-> $r)?>,)*
+            $($cname: Option<unsafe extern "C" fn($($ct),*) $(-> $cr)?>,)*
+        }
+
+        impl Functions {
+            unsafe fn resolve(handle: *mut c_void) -> Functions {
+                Functions {
+                    $($name: mem::transmute::<*mut c_void, Option<unsafe extern "C" fn($($t),*) $(-> $r)?>>(
+                        symbol(handle, concat!(stringify!($name), "\0"))),)*
+                    $($cname: mem::transmute::<*mut c_void, Option<unsafe extern "C" fn($($ct),*) $(-> $cr)?>>(
+                        symbol(handle, concat!(stringify!($cname), "\0"))),)*
+                }
+            }
+        }
+
+        $(pub unsafe extern "C" fn $name($($arg: $t),*) $(-> $r)? {
+            match library().and_then(|lib| lib.functions.$name.map(|f| (lib, f))) {
+                Some((lib, f)) => call(lib, || f($($arg),*)),
+                None => fallback::$name($($arg),*),
+            }
+        })*
+    };
+}
+
+variables! {
+    history_base: c_int = 1;
+    history_length: c_int = 0;
+    rl_line_buffer: *mut c_char = ptr::null_mut();
+    rl_point: c_int = 0;
+    rl_end: c_int = 0;
+    rl_done: c_int = 0;
+    rl_display_prompt: *mut c_char = ptr::null_mut();
+    rl_instream: *mut FILE = ptr::null_mut();
+    rl_outstream: *mut FILE = ptr::null_mut();
+    rl_inhibit_completion: c_int = 0;
+    rl_library_version: *const c_char = c"".as_ptr();
+    rl_readline_version: c_int = 0;
+    rl_readline_name: *const c_char = c"other".as_ptr();
+    rl_attempted_completion_over: c_int = 0;
+    rl_completer_word_break_characters: *const c_char = c" \t\n\"\\'`@$><=;|&{(".as_ptr();
+    rl_filename_completion_desired: c_int = 0;
+    rl_filename_quoting_desired: c_int = 1;
+    rl_completion_append_character: c_int = b' ' as c_int;
+    rl_completion_suppress_append: c_int = 0;
+    rl_completion_suppress_quote: c_int = 0;
+    rl_special_prefixes: *const c_char = ptr::null();
+    rl_completer_quote_characters: *const c_char = ptr::null();
+    rl_filename_quote_characters: *const c_char = ptr::null();
+    rl_basic_quote_characters: *const c_char = c"\"'".as_ptr();
+    rl_completion_query_items: c_int = 100;
+    rl_sort_completion_matches: c_int = 1;
+    rl_ignore_completion_duplicates: c_int = 1;
+    rl_completion_type: c_int = 0;
+    rl_catch_signals: c_int = 1;
+    rl_catch_sigwinch: c_int = 1;
+}
+
+hooks! {
+    rl_redisplay_function: VoidFunction = Some(rl_redisplay), fn();
+    rl_attempted_completion_function: CompletionFunction = None, fn(text: *const c_char, start: c_int, end: c_int) -> *mut *const c_char;
+    rl_directory_completion_hook: DirectoryHookFunction = None, fn(dirname: *mut *mut c_char) -> c_int;
+    rl_filename_rewrite_hook: DequoteFunction = None, fn(text: *mut c_char, quote_char: c_int) -> *mut c_char;
+    rl_completion_display_matches_hook: DisplayMatchesFunction = None, fn(matches: *mut *mut c_char, num_matches: c_int, max_length: c_int);
+    rl_char_is_quoted_p: LineBufFunction = None, fn(text: *mut c_char, index: c_int) -> c_int;
+    rl_filename_quoting_function: QuoteFunction = None, fn(text: *mut c_char, match_type: c_int, quote_pointer: *mut c_char) -> *mut c_char;
+    rl_filename_dequoting_function: DequoteFunction = None, fn(text: *mut c_char, quote_char: c_int) -> *mut c_char;
+    rl_startup_hook: HookFunction = None, fn() -> c_int;
+    rl_pre_input_hook: HookFunction = None, fn() -> c_int;
+    rl_event_hook: HookFunction = None, fn() -> c_int;
+    rl_signal_event_hook: HookFunction = None, fn() -> c_int;
+    rl_getc_function: GetcFunction = Some(rl_getc), fn(stream: *mut FILE) -> c_int;
+}
+
+functions! {
+    fn using_history();
+    fn add_history(line: *const c_char);
+    fn history_get(offset: c_int) -> *mut HistEntry;
+    fn clear_history();
+    fn where_history() -> c_int;
+    fn history_set_pos(pos: c_int) -> c_int;
+    fn current_history() -> *mut HistEntry;
+    fn history_search_prefix(string: *const c_char, direction: c_int) -> c_int;
+    fn read_history(filename: *const c_char) -> c_int;
+    fn write_history(filename: *const c_char) -> c_int;
+    fn history_truncate_file(filename: *const c_char, nlines: c_int) -> c_int;
+    fn append_history(nelements: c_int, filename: *const c_char) -> c_int;
+    fn stifle_history(max: c_int);
+    fn unstifle_history() -> c_int;
+    fn history_is_stifled() -> c_int;
+    fn readline(prompt: *const c_char) -> *const c_char;
+    fn rl_initialize() -> c_int;
+    fn rl_read_init_file(filename: *const c_char) -> c_int;
+    fn rl_parse_and_bind(line: *const c_char) -> c_int;
+    fn rl_callback_read_char();
+    fn rl_callback_handler_remove();
+    fn rl_set_keyboard_input_timeout(usecs: c_int) -> c_int;
+    fn rl_redisplay();
+    fn rl_set_prompt(prompt: *const c_char) -> c_int;
+    fn rl_insert_text(text: *const c_char) -> c_int;
+    fn rl_newline(count: c_int, key: c_int) -> c_int;
+    fn rl_ding() -> c_int;
+    fn rl_forced_update_display() -> c_int;
+    fn rl_clear_visible_line() -> c_int;
+    fn rl_variable_bind(variable: *const c_char, value: *const c_char) -> c_int;
+    fn rl_variable_value(variable: *const c_char) -> *const c_char;
+    fn rl_forward_char(count: c_int, key: c_int) -> c_int;
+    fn rl_get_screen_size(rows: *mut c_int, cols: *mut c_int);
+    fn rl_set_screen_size(rows: c_int, cols: c_int);
+    fn rl_resize_terminal();
+    fn rl_cleanup_after_signal();
+    fn rl_reset_after_signal();
+    fn rl_free_line_state();
+    fn rl_free_undo_list();
+    fn rl_display_match_list(matches: *mut *mut c_char, len: c_int, max: c_int);
+    fn rl_filename_completion_function(text: *const c_char, state: c_int) -> *const c_char;
+    fn rl_getc(stream: *mut FILE) -> c_int;
+    custom:
+    fn rl_callback_handler_install(prompt: *const c_char, handler: VCPFunction);
+    fn rl_add_defun(name: *const c_char, function: Option<CommandFunction>, key: c_int) -> c_int;
+    fn rl_bind_key(key: c_int, function: Option<CommandFunction>) -> c_int;
+    fn rl_bind_keyseq(keyseq: *const c_char, function: Option<CommandFunction>) -> c_int;
+    fn rl_function_of_keyseq(keyseq: *const c_char, map: Keymap, typ: *mut c_int) -> Option<CommandFunction>;
+    fn rl_completion_matches(text: *const c_char, entry_func: CompletionEntryFunction) -> *mut *const c_char;
+}
+
+struct Library {
+    variables: Variables,
+    hooks: Hooks,
+    functions: Functions,
+}
+
+// readline is not thread-safe: like the linked library, it must be used from one thread at a time
+unsafe impl Send for Library {}
+unsafe impl Sync for Library {}
+
+static LIBRARY: OnceLock<Option<Library>> = OnceLock::new();
+
+impl Library {
+    unsafe fn open() -> Option<Library> {
+        let handle = LIBRARIES.iter()
+            .map(|name| libc::dlopen(name.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL))
+            .find(|handle| !handle.is_null())?;
+        let mut library = Library {
+            variables: Variables::resolve(handle),
+            hooks: Hooks::resolve(handle),
+            functions: Functions::resolve(handle),
+        };
+        library.variables.init();
+        library.hooks.init();
+        Some(library)
+    }
+
+    unsafe fn push(&self) -> (Values, HookValues) {
+        (self.variables.push(), self.hooks.push())
+    }
+
+    unsafe fn pull(&self, pushed: Option<&(Values, HookValues)>) {
+        self.variables.pull(pushed.map(|pushed| &pushed.0));
+        self.hooks.pull(pushed.map(|pushed| &pushed.1));
+    }
+}
+
+/// Return the library, loading it the first time.
+fn library() -> Option<&'static Library> {
+    LIBRARY.get_or_init(|| unsafe { Library::open() }).as_ref()
+}
+
+/// Say if libreadline or libedit was found.
+pub fn available() -> bool {
+    library().is_some()
+}
+
+thread_local! {
+    // the values last given to the library by the innermost call, pushed again after each callback
+    static PUSHED: Cell<Option<(Values, HookValues)>> = const { Cell::new(None) };
+}
+
+/// Call a function of the library with the variables.
+unsafe fn call<R, F>(library: &Library, f: F) -> R where F: FnOnce() -> R {
+    let outer = PUSHED.with(|p| p.replace(Some(library.push())));
+    let result = f();
+    let pushed = PUSHED.with(|p| p.replace(outer));
+    library.pull(pushed.as_ref());
+    result
+}
+
+/// Run Rust code called back by the library with the variables.
+fn callback<R, F>(f: F) -> R where F: FnOnce() -> R {
+    let library = library().expect("callback without library");
+    unsafe { library.pull(None) };
+    let result = f();
+    let pushed = unsafe { library.push() };
+    PUSHED.with(|p| p.set(Some(pushed)));
+    result
+}
+
+/// Address of a function pointer, 0 for `None`.
+fn address<T>(f: Option<T>) -> usize {
+    assert_eq!(mem::size_of::<Option<T>>(), mem::size_of::<usize>());
+    unsafe { mem::transmute_copy::<Option<T>, usize>(&f) }
+}
+
+// Bound commands are given to the library as one of these trampolines,
+// the slot of the trampoline holding the Rust function.
+const SLOTS: usize = 16;
+
+static COMMANDS: Mutex<[Option<CommandFunction>; SLOTS]> = Mutex::new([None; SLOTS]);
+
+extern "C" fn command<const N: usize>(count: c_int, key: c_int) -> c_int {
+    let f = COMMANDS.lock().unwrap_or_else(|e| e.into_inner())[N];
+    callback(|| f.map_or(0, |f| f(count, key)))
+}
+
+const TRAMPOLINES: [CommandFunction; SLOTS] = [
+    command::<0>, command::<1>, command::<2>, command::<3>, command::<4>, command::<5>, command::<6>, command::<7>,
+    command::<8>, command::<9>, command::<10>, command::<11>, command::<12>, command::<13>, command::<14>, command::<15>,
+];
+
+/// Return the trampoline of `function`, giving it a slot the first time, or `Err` if there is no free slot.
+fn command_trampoline(function: Option<CommandFunction>) -> Result<Option<CommandFunction>, ()> {
+    let f = match function {
+        Some(f) => f,
+        None => return Ok(None),
+    };
+    if TRAMPOLINES.iter().any(|&t| t as usize == f as usize) {
+        return Ok(Some(f));
+    }
+    let mut commands = COMMANDS.lock().unwrap_or_else(|e| e.into_inner());
+    let slot = commands.iter().position(|c| c.is_some_and(|c| c as usize == f as usize))
+        .or_else(|| commands.iter().position(Option::is_none))
+        .ok_or(())?;
+    commands[slot] = Some(f);
+    Ok(Some(TRAMPOLINES[slot]))
+}
+
+/// Return the Rust function called by `function` if it is a trampoline.
+fn command_function(function: Option<CommandFunction>) -> Option<CommandFunction> {
+    let f = function?;
+    match TRAMPOLINES.iter().position(|&t| t as usize == f as usize) {
+        Some(slot) => COMMANDS.lock().unwrap_or_else(|e| e.into_inner())[slot],
+        None => Some(f),
+    }
+}
+
+pub unsafe extern "C" fn rl_add_defun(name: *const c_char, function: Option<CommandFunction>, key: c_int) -> c_int {
+    match (library().and_then(|lib| lib.functions.rl_add_defun.map(|f| (lib, f))), command_trampoline(function)) {
+        (Some((lib, f)), Ok(trampoline)) => call(lib, || f(name, trampoline, key)),
+        (Some(_), Err(())) => -1,
+        (None, _) => fallback::rl_add_defun(name, function, key),
+    }
+}
+
+pub unsafe extern "C" fn rl_bind_key(key: c_int, function: Option<CommandFunction>) -> c_int {
+    match (library().and_then(|lib| lib.functions.rl_bind_key.map(|f| (lib, f))), command_trampoline(function)) {
+        (Some((lib, f)), Ok(trampoline)) => call(lib, || f(key, trampoline)),
+        (Some(_), Err(())) => -1,
+        (None, _) => fallback::rl_bind_key(key, function),
+    }
+}
+
+pub unsafe extern "C" fn rl_bind_keyseq(keyseq: *const c_char, function: Option<CommandFunction>) -> c_int {
+    match (library().and_then(|lib| lib.functions.rl_bind_keyseq.map(|f| (lib, f))), command_trampoline(function)) {
+        (Some((lib, f)), Ok(trampoline)) => call(lib, || f(keyseq, trampoline)),
+        (Some(_), Err(())) => -1,
+        (None, _) => fallback::rl_bind_keyseq(keyseq, function),
+    }
+}
+
+pub unsafe extern "C" fn rl_function_of_keyseq(keyseq: *const c_char, map: Keymap, typ: *mut c_int) -> Option<CommandFunction> {
+    match library().and_then(|lib| lib.functions.rl_function_of_keyseq.map(|f| (lib, f))) {
+        Some((lib, f)) => command_function(call(lib, || f(keyseq, map, typ))),
+        None => fallback::rl_function_of_keyseq(keyseq, map, typ),
+    }
+}
+
+static ENTRY_FUNCTION: Mutex<Option<CompletionEntryFunction>> = Mutex::new(None);
+
+extern "C" fn entry_function(text: *const c_char, state: c_int) -> *const c_char {
+    let f = ENTRY_FUNCTION.lock().unwrap_or_else(|e| e.into_inner()).expect("no completion entry function");
+    callback(|| f(text, state))
+}
+
+pub unsafe extern "C" fn rl_completion_matches(text: *const c_char, entry_func: CompletionEntryFunction) -> *mut *const c_char {
+    match library().and_then(|lib| lib.functions.rl_completion_matches.map(|f| (lib, f))) {
+        Some((lib, f)) => {
+            let previous = ENTRY_FUNCTION.lock().unwrap_or_else(|e| e.into_inner()).replace(entry_func);
+            let matches = call(lib, || f(text, entry_function));
+            *ENTRY_FUNCTION.lock().unwrap_or_else(|e| e.into_inner()) = previous;
+            matches
+        }
+        None => fallback::rl_completion_matches(text, entry_func),
+    }
+}
+
+static LINE_HANDLER: Mutex<VCPFunction> = Mutex::new(None);
+
+extern "C" fn line_handler(line: *mut c_char) {
+    let handler = *LINE_HANDLER.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(handler) = handler {
+        callback(|| handler(line));
+    }
+}
+
+pub unsafe extern "C" fn rl_callback_handler_install(prompt: *const c_char, handler: VCPFunction) {
+    match library().and_then(|lib| lib.functions.rl_callback_handler_install.map(|f| (lib, f))) {
+        Some((lib, f)) => {
+            *LINE_HANDLER.lock().unwrap_or_else(|e| e.into_inner()) = handler;
+            let handler: Option<CompleteLineFunction> = handler.map(|_| line_handler as CompleteLineFunction);
+            call(lib, || f(prompt, handler))
+        }
+        None => fallback::rl_callback_handler_install(prompt, handler),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libc::c_int;
+
+    extern "C" fn noop(_count: c_int, _key: c_int) -> c_int {
+        0
+    }
+
+    #[test]
+    fn command_trampoline() {
+        let trampoline = super::command_trampoline(Some(noop)).unwrap();
+        assert!(trampoline.is_some_and(|t| t as usize != noop as *const () as usize));
+        assert_eq!(super::command_trampoline(Some(noop)).unwrap().map(|t| t as usize), trampoline.map(|t| t as usize));
+        assert_eq!(super::command_function(trampoline).map(|f| f as usize), Some(noop as *const () as usize));
+        assert!(super::command_trampoline(None).unwrap().is_none());
+    }
+}
@@ -0,0 +1,202 @@
+//! Readline's filename completion.
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::marker::PhantomData;
+use std::slice;
+use std::str;
+use libc::{c_char, c_int, c_void};
+
+use super::completion::{Completer, CompletionContext};
+use super::ffi;
+
+/// Complete `text` as a filename, the same way readline does by default.
+///
+/// Directories are followed by a slash and, if the filename quoting is desired,
+/// filenames with special characters are quoted when inserted.
+///
+/// It uses readline's state, so it is not `Send`: it cannot be run by a `ThreadedCompleter`.
+#[derive(Default)]
+pub struct FilenameCompleter(PhantomData<*const ()>);
+
+impl FilenameCompleter {
+    /// Complete filenames with readline's function, on the thread calling `readline()`.
+    pub fn new() -> FilenameCompleter {
+        FilenameCompleter(PhantomData)
+    }
+}
+
+impl Completer for FilenameCompleter {
+    fn complete(&self, context: &CompletionContext) -> Vec<String> {
+        rl_filename_completion_desired(true);
+        let c_text = CString::new(context.word.as_str()).unwrap();
+        let mut entries = Vec::new();
+        let mut state = 0;
+        loop {
+            let entry = rl_filename_completion_function(c_text.as_ptr(), state);
+            if entry.is_null() {
+                break;
+            }
+            entries.push(super::c_str_to_string(entry).unwrap());
+            unsafe { libc::free(entry as *mut c_void) };
+            state += 1;
+        }
+        entries
+    }
+}
+
+/// Generator of filenames matching `text`: the first call must be done with `state` 0.
+///
+/// It can be passed to `rl_completion_matches()`. Returned strings are `malloc`'d.
+/// (See [rl_filename_completion_function](http://cnswww.cns.cwru.edu/php/chet/readline/readline.html))
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn rl_filename_completion_function(text: *const c_char, state: c_int) -> *const c_char {
+    unsafe { ffi::rl_filename_completion_function(text, state) }
+}
+
+/// Tell readline that the matches are filenames: directories get a slash appended
+/// and the `rl_filename_rewrite_hook` applies. It is reset before each completion.
+///
+/// (See [rl_filename_completion_desired](http://cnswww.cns.cwru.edu/php/chet/readline/readline.html))
+pub fn rl_filename_completion_desired(b: bool) {
+    unsafe { ffi::rl_filename_completion_desired = b as i32; }
+}
+
+/// Tell readline to quote filename matches containing word break characters.
+/// It is reset (to true) before each completion.
+///
+/// (See [rl_filename_quoting_desired](http://cnswww.cns.cwru.edu/php/chet/readline/readline.html))
+pub fn rl_filename_quoting_desired(b: bool) {
+    unsafe { ffi::rl_filename_quoting_desired = b as i32; }
+}
+
+/// A hook receiving a name and returning its replacement, or `None` to leave it unchanged.
+pub type RewriteHook = Box<dyn FnMut(&str) -> Option<String>>;
+
+thread_local! {
+    static DIRECTORY_COMPLETION_HOOK: RefCell<Option<RewriteHook>> = const { RefCell::new(None) };
+    static FILENAME_REWRITE_HOOK: RefCell<Option<RewriteHook>> = const { RefCell::new(None) };
+}
+
+extern "C" fn directory_completion_hook(dirname: *mut *mut c_char) -> c_int {
+    if unsafe { *dirname }.is_null() {
+        return 0;
+    }
+    // a name which is not UTF-8 is left unchanged, rather than rewritten with replacement characters
+    let name = match unsafe { CStr::from_ptr(*dirname) }.to_str() {
+        Ok(name) => name,
+        Err(_) => return 0,
+    };
+    let new_name = DIRECTORY_COMPLETION_HOOK.with(|h| match *h.borrow_mut() {
+        Some(ref mut h) => h(name),
+        None => None,
+    });
+    match new_name {
+        Some(ref new_name) if *new_name != name => {
+            let c_new_name = CString::new(new_name.as_str()).unwrap();
+            unsafe {
+                libc::free(*dirname as *mut c_void);
+                *dirname = ffi::strdup(c_new_name.as_ptr()) as *mut c_char;
+            }
+            1
+        }
+        _ => 0,
+    }
+}
+
+extern "C" fn filename_rewrite_hook(fname: *mut c_char, fnlen: c_int) -> *mut c_char {
+    let bytes = unsafe { slice::from_raw_parts(fname as *const u8, fnlen as usize) };
+    let name = match str::from_utf8(bytes) {
+        Ok(name) => name,
+        Err(_) => return fname,
+    };
+    let new_name = FILENAME_REWRITE_HOOK.with(|h| match *h.borrow_mut() {
+        Some(ref mut h) => h(name),
+        None => None,
+    });
+    match new_name {
+        // readline frees the returned string when it is not `fname`
+        Some(ref new_name) if new_name != name => {
+            let c_new_name = CString::new(new_name.as_str()).unwrap();
+            unsafe { ffi::strdup(c_new_name.as_ptr()) as *mut c_char }
+        }
+        _ => fname,
+    }
+}
+
+/// Set the function called on the directory name of a filename being completed.
+///
+/// It may return the name to use instead, for example with `~` or variables expanded.
+/// (See [rl_directory_completion_hook](http://cnswww.cns.cwru.edu/php/chet/readline/readline.html))
+pub fn set_rl_directory_completion_hook(hook: Option<RewriteHook>) {
+    let installed = hook.is_some();
+    DIRECTORY_COMPLETION_HOOK.with(|h| *h.borrow_mut() = hook);
+    unsafe { ffi::rl_directory_completion_hook = if installed { Some(directory_completion_hook) } else { None } }
+}
+
+/// Set the function called on each filename read from the file system before it is compared to the word being completed.
+///
+/// It may return the name to use instead, for example with a different Unicode normalization.
+/// (See [rl_filename_rewrite_hook](http://cnswww.cns.cwru.edu/php/chet/readline/readline.html))
+pub fn set_rl_filename_rewrite_hook(hook: Option<RewriteHook>) {
+    let installed = hook.is_some();
+    FILENAME_REWRITE_HOOK.with(|h| *h.borrow_mut() = hook);
+    unsafe { ffi::rl_filename_rewrite_hook = if installed { Some(filename_rewrite_hook) } else { None } }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::{CStr, OsStr};
+    use std::fs::{self, File};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+    use libc::{c_char, c_void};
+
+    use super::super::ffi;
+
+    use super::super::completion::{Completer, CompletionContext};
+
+    #[test]
+    fn filename_completer() {
+        let td = tempdir::TempDir::new_in(Path::new("."), "files").unwrap();
+        File::create(td.path().join("alpha.txt")).unwrap();
+        File::create(td.path().join("alpine.txt")).unwrap();
+        fs::create_dir(td.path().join("beta")).unwrap();
+
+        let prefix = format!("{}/al", td.path().to_str().unwrap());
+        let mut entries = super::FilenameCompleter::new().complete(&CompletionContext::new(&prefix, 0, prefix.len()));
+        entries.sort();
+        assert_eq!(entries, vec![format!("{}/alpha.txt", td.path().to_str().unwrap()),
+                                 format!("{}/alpine.txt", td.path().to_str().unwrap())]);
+
+        td.close().unwrap();
+    }
+
+    #[test]
+    fn non_utf8_filename() {
+        let td = tempdir::TempDir::new_in(Path::new("."), "files").unwrap();
+        File::create(td.path().join(OsStr::from_bytes(b"caf\xe9"))).unwrap();
+
+        let prefix = format!("{}/ca", td.path().to_str().unwrap());
+        let entries = super::FilenameCompleter::new().complete(&CompletionContext::new(&prefix, 0, prefix.len()));
+        assert_eq!(entries, vec![format!("{}/caf\u{fffd}", td.path().to_str().unwrap())]);
+
+        td.close().unwrap();
+    }
+
+    #[test]
+    fn directory_completion_hook() {
+        super::set_rl_directory_completion_hook(Some(Box::new(|name| Some(name.replace("~", "/home")))));
+        let mut dirname = unsafe { ffi::strdup(c"~/docs".as_ptr()) } as *mut c_char;
+        assert_eq!(super::directory_completion_hook(&mut dirname), 1);
+        assert_eq!(unsafe { CStr::from_ptr(dirname) }, c"/home/docs");
+        unsafe { libc::free(dirname as *mut c_void) };
+
+        // not UTF-8: unchanged
+        let mut dirname = unsafe { ffi::strdup(c"~/caf\xe9".as_ptr()) } as *mut c_char;
+        assert_eq!(super::directory_completion_hook(&mut dirname), 0);
+        assert_eq!(unsafe { CStr::from_ptr(dirname) }, c"~/caf\xe9");
+        unsafe { libc::free(dirname as *mut c_void) };
+        super::set_rl_directory_completion_hook(None);
+    }
+}
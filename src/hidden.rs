@@ -0,0 +1,143 @@
+//! Reading secrets (passwords, tokens) without echoing them.
+
+use std::cell::Cell;
+use std::ffi::CString;
+use std::fmt;
+use std::ptr;
+use std::slice;
+use libc::c_void;
+
+use super::display;
+use super::ffi;
+use super::plain;
+
+/// A line read by `readline_hidden()`.
+///
+/// The memory holding the secret is zeroed when the value is dropped.
+pub struct SecretString(String);
+
+impl SecretString {
+    /// Give access to the secret.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for SecretString {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("SecretString(***)")
+    }
+}
+
+impl Drop for SecretString {
+    fn drop(&mut self) {
+        unsafe { zero(self.0.as_mut_vec()) }
+    }
+}
+
+fn zero(bytes: &mut [u8]) {
+    for b in bytes.iter_mut() {
+        // volatile so that the write is not optimized away
+        unsafe { ptr::write_volatile(b, 0) };
+    }
+}
+
+thread_local! {
+    static MASK: Cell<Option<char>> = const { Cell::new(None) };
+}
+
+/// What is displayed in place of `line`.
+fn masked(line: &[u8], mask: Option<char>) -> String {
+    match mask {
+        // count the characters without decoding the secret
+        Some(mask) => line.iter().filter(|&&b| b & 0xC0 != 0x80).map(|_| mask).collect(),
+        None => String::new(),
+    }
+}
+
+extern "C" fn hidden_redisplay() {
+    let mask = MASK.with(|mask| mask.get());
+    let line = unsafe { slice::from_raw_parts(ffi::rl_line_buffer as *const u8, ffi::rl_end as usize) };
+    let point = unsafe { ffi::rl_point as usize }.min(line.len());
+    display::draw(&display::display_prompt(), &masked(line, mask), &masked(&line[..point], mask));
+}
+
+/// Print a `prompt` and read a line of text without echoing it.
+///
+/// Each typed character is displayed as `mask` or, if `mask` is `None`, nothing is displayed at all.
+/// Completion is disabled and the line is not added to the history.
+/// `None` is returned at end of file, and also when the edited line is not valid UTF-8
+/// (a byte typed in the C locale or on a serial line): it is zeroed and dropped.
+/// When lines are not read interactively (see `Mode`), the line is read from stdin like any other.
+///
+/// What is wiped: the returned secret when it is dropped, readline's line buffer and the string it returns,
+/// and the bytes of a line read plainly. Readline's undo list is freed, but its records may have been freed
+/// already by readline without being zeroed. Neither are the kernel's terminal buffers, nor, for a line read plainly,
+/// the buffer of stdin (or of the `ReadlineSession` stream) and the smaller copies left while the line grew.
+pub fn readline_hidden(prompt: &str, mask: Option<char>) -> Option<SecretString> {
+    if !plain::is_interactive() {
+        return plain::readline_bytes(prompt).and_then(secret_string);
+    }
+    let c_prompt = CString::new(prompt).unwrap();
+    MASK.with(|m| m.set(mask));
+    display::reset();
+    let c_line = unsafe {
+        let redisplay = ffi::rl_redisplay_function;
+        let inhibit_completion = ffi::rl_inhibit_completion;
+        ffi::rl_redisplay_function = Some(hidden_redisplay);
+        ffi::rl_inhibit_completion = 1;
+        let c_line = ffi::readline(c_prompt.as_ptr());
+        ffi::rl_redisplay_function = redisplay;
+        ffi::rl_inhibit_completion = inhibit_completion;
+        if !ffi::rl_line_buffer.is_null() {
+            libc::memset(ffi::rl_line_buffer as *mut c_void, 0, ffi::rl_end as usize);
+        }
+        // the text of each edit, until the next line
+        ffi::rl_free_undo_list();
+        c_line
+    };
+    if c_line.is_null() {  // user pressed Ctrl-D
+        return None;
+    }
+    let secret = unsafe {
+        let len = libc::strlen(c_line);
+        let bytes = slice::from_raw_parts(c_line as *const u8, len).to_vec();
+        libc::memset(c_line as *mut c_void, 0, len);
+        libc::free(c_line as *mut c_void);
+        bytes
+    };
+    secret_string(secret)
+}
+
+/// Return `bytes` as a secret, or zero them and return `None` if they are not UTF-8.
+fn secret_string(bytes: Vec<u8>) -> Option<SecretString> {
+    match String::from_utf8(bytes) {
+        Ok(secret) => Some(SecretString(secret)),
+        Err(err) => {
+            zero(&mut err.into_bytes());
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn masked() {
+        assert_eq!(super::masked("pässword".as_bytes(), Some('*')), "********");
+        assert_eq!(super::masked(b"password", None), "");
+    }
+
+    #[test]
+    fn secret_string() {
+        assert_eq!(super::secret_string("pässword".as_bytes().to_vec()).map(|s| s.as_str().to_string()), Some("pässword".to_string()));
+        assert!(super::secret_string(b"p\xe4ssword".to_vec()).is_none());
+    }
+
+    #[test]
+    fn secret_debug() {
+        let secret = super::SecretString("hunter2".to_string());
+        assert_eq!(format!("{:?}", secret), "SecretString(***)");
+        assert_eq!(secret.as_str(), "hunter2");
+    }
+}
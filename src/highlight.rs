@@ -0,0 +1,59 @@
+//! Syntax highlighting of the line being edited.
+
+use std::cell::RefCell;
+
+use super::display;
+
+/// Colorize the line being edited.
+pub trait Highlighter {
+    /// Return `line` decorated with ANSI escape sequences, `pos` being the point (a byte offset in `line`).
+    ///
+    /// Only escape sequences may be added: the visible characters must be those of `line`.
+    fn highlight(&self, line: &str, pos: usize) -> String;
+
+    /// Return the `hint` displayed after the line decorated with ANSI escape sequences.
+    ///
+    /// By default, the hint is dimmed.
+    fn highlight_hint(&self, hint: &str) -> String {
+        format!("\x1b[2m{}\x1b[0m", hint)
+    }
+}
+
+impl<F> Highlighter for F where F: Fn(&str, usize) -> String {
+    fn highlight(&self, line: &str, pos: usize) -> String {
+        self(line, pos)
+    }
+}
+
+thread_local! {
+    static HIGHLIGHTER: RefCell<Option<Box<dyn Highlighter>>> = const { RefCell::new(None) };
+}
+
+/// Decorate `line` with the current highlighter, if any.
+pub fn highlight(line: &str, pos: usize) -> String {
+    HIGHLIGHTER.with(|h| match *h.borrow() {
+        Some(ref h) => h.highlight(line, pos),
+        None => line.to_string(),
+    })
+}
+
+/// Decorate `hint` with the current highlighter or dim it.
+pub fn highlight_hint(hint: &str) -> String {
+    HIGHLIGHTER.with(|h| match *h.borrow() {
+        Some(ref h) => h.highlight_hint(hint),
+        None => format!("\x1b[2m{}\x1b[0m", hint),
+    })
+}
+
+/// Set the `Highlighter` used to display the line being edited.
+///
+/// It replaces readline's redisplay function; `None` restores the default one (unless a `Hinter` is set).
+pub fn set_highlighter(highlighter: Option<Box<dyn Highlighter>>) {
+    HIGHLIGHTER.with(|h| *h.borrow_mut() = highlighter);
+    display::update_redisplay_function();
+}
+
+/// Say if a highlighter is set.
+pub fn has_highlighter() -> bool {
+    HIGHLIGHTER.with(|h| h.borrow().is_some())
+}
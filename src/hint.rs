@@ -0,0 +1,126 @@
+//! Fish-style suggestions displayed after the cursor.
+
+use std::cell::{Cell, RefCell};
+use std::ffi::CString;
+use libc::c_int;
+
+use super::display;
+use super::ffi;
+
+/// Suggest how the line being edited may be completed.
+pub trait Hinter {
+    /// Return the text to display after `line`, `pos` being the point (a byte offset in `line`).
+    fn hint(&self, line: &str, pos: usize) -> Option<String>;
+}
+
+impl<F> Hinter for F where F: Fn(&str, usize) -> Option<String> {
+    fn hint(&self, line: &str, pos: usize) -> Option<String> {
+        self(line, pos)
+    }
+}
+
+/// Suggest the rest of the newest history entry starting with the line, when the point is at the end of the line.
+pub struct HistoryHinter;
+
+impl Hinter for HistoryHinter {
+    fn hint(&self, line: &str, pos: usize) -> Option<String> {
+        if line.is_empty() || pos < line.len() {
+            return None;
+        }
+        let c_line = CString::new(line).ok()?;
+        let entry = unsafe {
+            // history_search_prefix moves the current history position
+            let offset = ffi::where_history();
+            ffi::history_set_pos(ffi::history_length - 1);
+            let entry = if ffi::history_search_prefix(c_line.as_ptr(), -1) == 0 {
+                let entry = ffi::current_history();
+                // an entry which is not UTF-8 is hinted with replacement characters, as the line is displayed
+                if entry.is_null() { None } else { super::c_str_to_string((*entry).line) }
+            } else {
+                None
+            };
+            ffi::history_set_pos(offset);
+            entry
+        };
+        entry.map(|entry| entry[line.len()..].to_string()).filter(|hint| !hint.is_empty())
+    }
+}
+
+thread_local! {
+    static HINTER: RefCell<Option<Box<dyn Hinter>>> = const { RefCell::new(None) };
+    static BOUND: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Return the hint for `line` given by the current hinter, if any.
+pub fn hint(line: &str, pos: usize) -> Option<String> {
+    HINTER.with(|h| match *h.borrow() {
+        Some(ref h) => h.hint(line, pos),
+        None => None,
+    })
+}
+
+/// Say if a hinter is set.
+pub fn has_hinter() -> bool {
+    HINTER.with(|h| h.borrow().is_some())
+}
+
+/// Insert the hint when the point is at the end of the line, move forward a character otherwise.
+extern "C" fn accept_hint(count: c_int, key: c_int) -> c_int {
+    let (line, point) = display::line_buffer();
+    if point == line.len() {
+        if let Some(hint) = hint(&line, point) {
+            let c_hint = CString::new(hint).unwrap();
+            unsafe { ffi::rl_insert_text(c_hint.as_ptr()) };
+            return 0;
+        }
+    }
+    unsafe { ffi::rl_forward_char(count, key) }
+}
+
+/// Set the `Hinter` whose suggestions are displayed (dimmed) after the cursor.
+///
+/// The suggestion is accepted with Right-arrow at the end of the line; the `accept-hint` command
+/// can also be bound to other keys in the inputrc file.
+/// It replaces readline's redisplay function; `None` restores the default one (unless a `Highlighter` is set).
+pub fn set_hinter(hinter: Option<Box<dyn Hinter>>) {
+    let install = hinter.is_some() && !BOUND.with(|bound| bound.replace(true));
+    if install {
+        let name = CString::new("accept-hint").unwrap();
+        unsafe {
+            // the name is kept by readline
+            ffi::rl_add_defun(ffi::strdup(name.as_ptr()), Some(accept_hint), -1);
+            for seq in &["\\e[C", "\\eOC"] {
+                let c_seq = CString::new(*seq).unwrap();
+                ffi::rl_bind_keyseq(c_seq.as_ptr(), Some(accept_hint));
+            }
+        }
+    }
+    HINTER.with(|h| *h.borrow_mut() = hinter);
+    display::update_redisplay_function();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Hinter;
+
+    #[test]
+    fn history_hinter() {
+        super::super::rl_initialize().unwrap();
+        super::super::clear_history();
+        super::super::add_history("select * from users");
+        super::super::add_history("select 1");
+        super::super::add_history("show tables");
+
+        let hinter = super::HistoryHinter;
+        assert_eq!(hinter.hint("sel", 3), Some("ect 1".to_string()));
+        assert_eq!(hinter.hint("select *", 8), Some(" from users".to_string()));
+        assert_eq!(hinter.hint("sel", 1), None);
+        assert_eq!(hinter.hint("show tables", 11), None);
+        assert_eq!(hinter.hint("", 0), None);
+
+        // read from a history file of another encoding for example
+        unsafe { super::ffi::add_history(c"cat caf\xe9.txt".as_ptr()) };
+        assert_eq!(hinter.hint("cat", 3), Some(" caf\u{fffd}.txt".to_string()));
+        super::super::clear_history();
+    }
+}
@@ -0,0 +1,221 @@
+//! Rust closures for the readline hook variables.
+//!
+//! Readline only knows about C function pointers, so each hook is stored in a
+//! thread-local slot and readline is given a trampoline that calls it back.
+//! Hooks must therefore be set from the thread calling `readline()`.
+
+use std::cell::{Cell, RefCell};
+use std::thread::LocalKey;
+use libc::{c_int, FILE};
+
+use super::ffi;
+
+/// A hook called by readline without any argument.
+pub type Hook = Box<dyn FnMut()>;
+
+/// A function giving readline its input, a byte at a time: `None` is the end of the input.
+pub type GetcFunction = Box<dyn FnMut() -> Option<u8>>;
+
+struct Slot<H = Hook> {
+    hook: Option<H>,
+    // bumped each time the hook is replaced, so that a hook can replace or remove itself
+    generation: usize,
+}
+
+thread_local! {
+    static STARTUP_HOOK: RefCell<Slot> = const { RefCell::new(Slot { hook: None, generation: 0 }) };
+    static PRE_INPUT_HOOK: RefCell<Slot> = const { RefCell::new(Slot { hook: None, generation: 0 }) };
+    static EVENT_HOOK: RefCell<Slot> = const { RefCell::new(Slot { hook: None, generation: 0 }) };
+    static SIGNAL_EVENT_HOOK: RefCell<Slot> = const { RefCell::new(Slot { hook: None, generation: 0 }) };
+    static GETC_FUNCTION: RefCell<Slot<GetcFunction>> = const { RefCell::new(Slot { hook: None, generation: 0 }) };
+    // readline's own function, restored when the Rust one is removed
+    static DEFAULT_GETC_FUNCTION: Cell<Option<Option<ffi::GetcFunction>>> = const { Cell::new(None) };
+}
+
+fn set_hook(slot: &'static LocalKey<RefCell<Slot>>, hook: Option<Hook>, trampoline: ffi::HookFunction) -> Option<ffi::HookFunction> {
+    slot.with(|slot| {
+        let mut slot = slot.borrow_mut();
+        slot.hook = hook;
+        slot.generation += 1;
+        slot.hook.as_ref().map(|_| trampoline)
+    })
+}
+
+fn call_hook(slot: &'static LocalKey<RefCell<Slot>>) -> c_int {
+    call(slot, |hook| hook());
+    0
+}
+
+/// Call the hook in `slot`, if any, with `f`.
+fn call<H, R>(slot: &'static LocalKey<RefCell<Slot<H>>>, f: impl FnOnce(&mut H) -> R) -> Option<R> {
+    // The hook is taken out of its slot while it runs so that it may call `set_rl_*`.
+    let taken = slot.with(|slot| {
+        let mut slot = slot.borrow_mut();
+        let generation = slot.generation;
+        slot.hook.take().map(|hook| (hook, generation))
+    });
+    taken.map(|(mut hook, generation)| {
+        let result = f(&mut hook);
+        slot.with(|slot| {
+            let mut slot = slot.borrow_mut();
+            if slot.generation == generation {
+                slot.hook = Some(hook);
+            }
+        });
+        result
+    })
+}
+
+extern "C" fn startup_hook() -> c_int {
+    call_hook(&STARTUP_HOOK)
+}
+
+extern "C" fn pre_input_hook() -> c_int {
+    call_hook(&PRE_INPUT_HOOK)
+}
+
+extern "C" fn event_hook() -> c_int {
+    call_hook(&EVENT_HOOK)
+}
+
+extern "C" fn signal_event_hook() -> c_int {
+    call_hook(&SIGNAL_EVENT_HOOK)
+}
+
+/// Set the function called just before readline prints the first prompt.
+///
+/// (See [rl_startup_hook](http://cnswww.cns.cwru.edu/php/chet/readline/readline.html))
+pub fn set_rl_startup_hook(hook: Option<Hook>) {
+    let f = set_hook(&STARTUP_HOOK, hook, startup_hook);
+    unsafe { ffi::rl_startup_hook = f }
+}
+
+/// Set the function called after the first prompt has been printed and just before readline starts reading input characters.
+///
+/// (See [rl_pre_input_hook](http://cnswww.cns.cwru.edu/php/chet/readline/readline.html))
+pub fn set_rl_pre_input_hook(hook: Option<Hook>) {
+    let f = set_hook(&PRE_INPUT_HOOK, hook, pre_input_hook);
+    unsafe { ffi::rl_pre_input_hook = f }
+}
+
+/// Set the function called periodically when readline is waiting for terminal input.
+///
+/// By default, this will be called at most ten times a second if there is no keyboard input
+/// (see `rl_set_keyboard_input_timeout()`).
+/// (See [rl_event_hook](http://cnswww.cns.cwru.edu/php/chet/readline/readline.html))
+pub fn set_rl_event_hook(hook: Option<Hook>) {
+    let f = set_hook(&EVENT_HOOK, hook, event_hook);
+    unsafe { ffi::rl_event_hook = f }
+}
+
+/// Set the function called when a read system call is interrupted by a signal and readline is not handling it.
+///
+/// (See [rl_signal_event_hook](http://cnswww.cns.cwru.edu/php/chet/readline/readline.html))
+pub fn set_rl_signal_event_hook(hook: Option<Hook>) {
+    let f = set_hook(&SIGNAL_EVENT_HOOK, hook, signal_event_hook);
+    unsafe { ffi::rl_signal_event_hook = f }
+}
+
+extern "C" fn getc_function(_stream: *mut FILE) -> c_int {
+    call(&GETC_FUNCTION, |f| f()).flatten().map_or(libc::EOF, c_int::from)
+}
+
+/// Set the function readline reads its input from, instead of `rl_instream`.
+///
+/// Keys can then come from a recorded macro, a script, a network stream or an event queue.
+/// The line is edited even if stdin is not a terminal (see `Mode`).
+/// The event hook should not be set meanwhile: GNU readline reads the terminal while it waits for input.
+/// `None` restores readline's own function.
+/// (See [rl_getc_function](http://cnswww.cns.cwru.edu/php/chet/readline/readline.html))
+pub fn set_rl_getc_function(f: Option<GetcFunction>) {
+    let installed = f.is_some();
+    GETC_FUNCTION.with(|g| {
+        let mut g = g.borrow_mut();
+        g.hook = f;
+        g.generation += 1;
+    });
+    let default = DEFAULT_GETC_FUNCTION.with(|d| {
+        let default = d.get().unwrap_or(unsafe { ffi::rl_getc_function });
+        d.set(Some(default));
+        default
+    });
+    unsafe { ffi::rl_getc_function = if installed { Some(getc_function) } else { default } }
+}
+
+/// Say if a function set with `set_rl_getc_function` gives readline its input.
+pub fn has_getc_function() -> bool {
+    GETC_FUNCTION.with(|g| g.borrow().hook.is_some())
+}
+
+/// Set the time interval, in microseconds, that readline waits for keyboard input before calling the event hook.
+///
+/// Returns the old timeout value.
+/// (See [rl_set_keyboard_input_timeout](http://cnswww.cns.cwru.edu/php/chet/readline/readline.html))
+pub fn rl_set_keyboard_input_timeout(usecs: i32) -> i32 {
+    unsafe { ffi::rl_set_keyboard_input_timeout(usecs) }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    use super::super::ffi;
+
+    #[test]
+    fn startup_hook() {
+        let calls = Rc::new(Cell::new(0));
+        let counter = calls.clone();
+        super::set_rl_startup_hook(Some(Box::new(move || counter.set(counter.get() + 1))));
+        let f = unsafe { ffi::rl_startup_hook }.expect("trampoline not installed");
+        f();
+        f();
+        assert_eq!(calls.get(), 2);
+
+        super::set_rl_startup_hook(None);
+        assert!(unsafe { ffi::rl_startup_hook }.is_none());
+    }
+
+    #[test]
+    fn getc_function() {
+        let default = unsafe { ffi::rl_getc_function }.map(|f| f as usize);
+        let mut keys = b"ab".to_vec().into_iter();
+        super::set_rl_getc_function(Some(Box::new(move || keys.next())));
+        assert!(super::has_getc_function());
+        let f = unsafe { ffi::rl_getc_function }.expect("trampoline not installed");
+        let keys: Vec<_> = (0..3).map(|_| unsafe { f(std::ptr::null_mut()) }).collect();
+        assert_eq!(keys, [b'a' as i32, b'b' as i32, libc::EOF]);
+
+        super::set_rl_getc_function(None);
+        assert!(!super::has_getc_function());
+        assert_eq!(unsafe { ffi::rl_getc_function }.map(|f| f as usize), default);
+    }
+
+    #[test]
+    fn event_hook_removes_itself() {
+        let calls = Rc::new(Cell::new(0));
+        let counter = calls.clone();
+        super::set_rl_event_hook(Some(Box::new(move || {
+            counter.set(counter.get() + 1);
+            super::set_rl_event_hook(None);
+        })));
+        super::event_hook();
+        super::event_hook();
+        assert_eq!(calls.get(), 1);
+        assert!(unsafe { ffi::rl_event_hook }.is_none());
+    }
+
+    #[test]
+    fn getc_function_removes_itself() {
+        let default = unsafe { ffi::rl_getc_function }.map(|f| f as usize);
+        super::set_rl_getc_function(Some(Box::new(|| {
+            super::set_rl_getc_function(None);
+            Some(b'a')
+        })));
+        let f = unsafe { ffi::rl_getc_function }.expect("trampoline not installed");
+        assert_eq!(unsafe { f(std::ptr::null_mut()) }, b'a' as i32);
+        assert_eq!(unsafe { f(std::ptr::null_mut()) }, libc::EOF);
+        assert!(!super::has_getc_function());
+        assert_eq!(unsafe { ffi::rl_getc_function }.map(|f| f as usize), default);
+    }
+}
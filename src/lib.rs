@@ -1,98 +1,184 @@
 #![crate_type = "lib"]
 
-#![feature(libc)]
-
-extern crate libc;
-
+use std::cell::RefCell;
 use std::ffi::CStr;
 use std::ffi::CString;
 use std::path::Path;
 use std::io::{Error, Result};
-use std::mem;
 use std::ptr;
 use std::str;
-use libc::c_void;
+use libc::{c_char, c_int, c_void};
 
-pub type CompletionFunction = extern "C" fn(text: *const i8, start: i32, end: i32) -> *mut *const i8;
+pub type CompletionFunction = extern "C" fn(text: *const c_char, start: c_int, end: c_int) -> *mut *const c_char;
 pub type CPPFunction = Option<CompletionFunction>;
 // rl_compentry_func_t
-pub type CompletionEntryFunction = extern "C" fn(text: *const i8, state: i32) -> *const i8;
-pub type CompleteLineFunction = extern "C" fn(text: *mut i8);
+pub type CompletionEntryFunction = extern "C" fn(text: *const c_char, state: c_int) -> *const c_char;
+pub type CompleteLineFunction = extern "C" fn(text: *mut c_char);
 pub type VCPFunction = Option<CompleteLineFunction>;
 
-static mut ENTRIES: *mut *const i8 = 0 as *mut *const i8;
-static mut NB_ENTRIES: usize = 0;
-static mut MAX_ENTRIES: usize = 0;
-
-fn clear_compentries() {
-    unsafe {
-        /* freed by readline
-        for i in range(0, NB_ENTRIES) {
-            libc::free(*ENTRIES.offset(i as isize) as *mut c_void);
-        }*/
-        NB_ENTRIES = 0;
-    }
-}
-fn alloc_compentries(n: usize) -> *mut *const i8 {
-    unsafe {
-        if n > MAX_ENTRIES {
-            ENTRIES = libc::realloc(ENTRIES as *mut c_void, (n * mem::size_of::<*const i8>()) as u64) as *mut *const i8;
-            if ENTRIES.is_null() {
-                panic!("Memory allocation failed.");
-            }
-            MAX_ENTRIES = n;
-        }
-        NB_ENTRIES = n;
-        ENTRIES
-    }
+thread_local! {
+    static COMPENTRIES: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
 }
+
+/// Set the entries returned by `get_compentry`, replacing the previous ones.
+///
+/// They are only forgotten once `get_compentry` has returned them all: a completion interrupted before
+/// may leave stale entries, which is why a `Completer` or a `MatchList` should be used instead.
+#[deprecated(note = "use `set_completer` with a `Completer`, or return a `MatchList` from the attempted completion function")]
 pub fn set_compentries(entries: Vec<String>) {
-    clear_compentries();
-    let c_entries = alloc_compentries(entries.len());
-    for i in 0..entries.len() {
-        let c_entry = CString::new(entries[i].as_bytes()).unwrap();
-        unsafe { *c_entries.offset(i as isize) = ffi::strdup(c_entry.as_ptr()); }
-    }
+    COMPENTRIES.with(|e| *e.borrow_mut() = entries);
 }
-pub fn get_compentry(i: usize) -> *const i8 {
-    unsafe {
-        if i >= NB_ENTRIES {
-            clear_compentries();
-            ptr::null()
-        } else {
-            *ENTRIES.offset(i as isize)
+/// Return a `malloc`'d copy of the entry `i` (freed by readline), or `NULL` after the last one
+/// (and for an entry containing a NUL byte).
+///
+/// It can be called from a completion entry function given to `rl_completion_matches`, with its `state`.
+#[deprecated(note = "use `set_completer` with a `Completer`, or return a `MatchList` from the attempted completion function")]
+pub fn get_compentry(i: usize) -> *const c_char {
+    COMPENTRIES.with(|e| {
+        let mut entries = e.borrow_mut();
+        match entries.get(i) {
+            Some(entry) => match CString::new(entry.as_bytes()) {
+                Ok(c_entry) => unsafe { ffi::strdup(c_entry.as_ptr()) },
+                Err(_) => ptr::null(),
+            },
+            None => {
+                entries.clear();
+                ptr::null()
+            }
         }
-    }
-}
+    })
+}
+
+pub use cache::CachedCompleter;
+pub use command_tree::{Arg, CommandTree, DynamicValues};
+pub use completion::{Completer, CompletionContext, set_completer, DisplayMatchesHook, set_rl_completion_display_matches_hook, display_match_list};
+pub use display::{RL_PROMPT_START_IGNORE, RL_PROMPT_END_IGNORE};
+pub use filename::{FilenameCompleter, RewriteHook, rl_filename_completion_function, rl_filename_completion_desired,
+                   rl_filename_quoting_desired, set_rl_directory_completion_hook, set_rl_filename_rewrite_hook};
+pub use hidden::{SecretString, readline_hidden};
+pub use highlight::{Highlighter, set_highlighter};
+pub use hint::{Hinter, HistoryHinter, set_hinter};
+pub use prompt::{Prompt, set_prompt};
+pub use match_list::MatchList;
+pub use matcher::{Matcher, common_prefix};
+pub use quoting::{QuoteStyle, QuotingFunction, DequotingFunction, CharIsQuotedFunction, SPECIAL_CHARACTERS,
+                  backslash_quote, single_quote, dequote, char_is_quoted, set_quote_style,
+                  set_rl_filename_quoting_function, set_rl_filename_dequoting_function, set_rl_char_is_quoted_p};
+pub use session::ReadlineSession;
+pub use plain::{Mode, set_mode, is_interactive, set_suppress_prompt};
+pub use multiline::{ValidationResult, Validator, set_validator, set_continuation_prompt, readline_multiline};
+pub use worker::ThreadedCompleter;
+pub use signal::{Interrupted, readline_interruptible, rl_catch_signals, set_rl_catch_signals, rl_catch_sigwinch, set_rl_catch_sigwinch,
+                 rl_cleanup_after_signal, rl_free_line_state, rl_reset_after_signal, rl_resize_terminal,
+                 rl_set_screen_size, rl_get_screen_size};
+pub use hooks::{Hook, set_rl_startup_hook, set_rl_pre_input_hook, set_rl_event_hook, set_rl_signal_event_hook, rl_set_keyboard_input_timeout,
+                GetcFunction, set_rl_getc_function, has_getc_function};
+
+mod cache;
+mod command_tree;
+mod completion;
+mod display;
+mod filename;
+mod hidden;
+mod highlight;
+mod hint;
+mod match_list;
+mod matcher;
+mod multiline;
+mod plain;
+mod prompt;
+mod quoting;
+mod session;
+mod signal;
+#[cfg(feature = "testing")]
+pub mod testing;
+mod worker;
+mod hooks;
+#[cfg(feature = "libedit")]
+mod libedit;
+#[cfg(feature = "pure-rust")]
+mod pure;
+#[cfg(feature = "dynamic")]
+mod dynamic;
+
+#[cfg(all(feature = "libedit", feature = "pure-rust"))]
+compile_error!("the `libedit` and `pure-rust` features cannot be enabled together");
+#[cfg(all(feature = "dynamic", any(feature = "libedit", feature = "pure-rust")))]
+compile_error!("the `dynamic` feature cannot be enabled with `libedit` or `pure-rust`");
 
 mod ffi {
-    use libc::{c_char, c_int};
+    use libc::{c_char, c_int, c_void, FILE};
+
+    pub type Keymap = *mut c_void;
+
+    // rl_voidfunc_t
+    pub type VoidFunction = unsafe extern "C" fn();
+    // rl_command_func_t
+    pub type CommandFunction = extern "C" fn(count: c_int, key: c_int) -> c_int;
+    // rl_icppfunc_t
+    pub type DirectoryHookFunction = extern "C" fn(dirname: *mut *mut c_char) -> c_int;
+    // rl_dequote_func_t
+    pub type DequoteFunction = extern "C" fn(text: *mut c_char, quote_char: c_int) -> *mut c_char;
+    // rl_compdisp_func_t
+    pub type DisplayMatchesFunction = extern "C" fn(matches: *mut *mut c_char, num_matches: c_int, max_length: c_int);
+    // rl_linebuf_func_t
+    pub type LineBufFunction = extern "C" fn(text: *mut c_char, index: c_int) -> c_int;
+    // rl_quote_func_t
+    pub type QuoteFunction = extern "C" fn(text: *mut c_char, match_type: c_int, quote_pointer: *mut c_char) -> *mut c_char;
+    // rl_hook_func_t
+    pub type HookFunction = extern "C" fn() -> c_int;
+    // rl_getc_func_t
+    pub type GetcFunction = unsafe extern "C" fn(stream: *mut FILE) -> c_int;
 
     #[repr(C)]
     pub struct HistEntry {
         pub line: *const c_char,
     }
 
-    #[link(name = "readline")]
-    extern {
+    // linked by build.rs
+    #[cfg(not(any(feature = "pure-rust", feature = "dynamic")))]
+    extern "C" {
         pub static mut history_base: c_int;
         pub static mut history_length: c_int;
         pub static mut rl_line_buffer: *mut c_char;
         pub static mut rl_point: c_int;
+        pub static mut rl_end: c_int;
+        pub static mut rl_done: c_int;
+        pub static mut rl_display_prompt: *mut c_char;
+        pub static mut rl_instream: *mut FILE;
+        pub static mut rl_outstream: *mut FILE;
+        pub static mut rl_redisplay_function: Option<VoidFunction>;
+        pub static mut rl_inhibit_completion: c_int;
         pub static rl_library_version: *const c_char;
         pub static rl_readline_version: c_int;
         pub static mut rl_readline_name: *const c_char;
         pub static mut rl_attempted_completion_function: super::CPPFunction;
         pub static mut rl_attempted_completion_over: c_int;
         pub static mut rl_completer_word_break_characters: *const c_char;
-        //pub static mut rl_completion_append_character: c_int;
-        //pub static mut rl_special_prefixes: *const c_char;
+        pub static mut rl_filename_completion_desired: c_int;
+        pub static mut rl_directory_completion_hook: Option<DirectoryHookFunction>;
+        pub static mut rl_completion_display_matches_hook: Option<DisplayMatchesFunction>;
+        pub static mut rl_completion_append_character: c_int;
+        pub static mut rl_completion_suppress_append: c_int;
+        pub static mut rl_special_prefixes: *const c_char;
+        pub static mut rl_completer_quote_characters: *const c_char;
+        pub static mut rl_basic_quote_characters: *const c_char;
+        pub static mut rl_completion_query_items: c_int;
+        pub static mut rl_sort_completion_matches: c_int;
+        pub static mut rl_ignore_completion_duplicates: c_int;
+        pub static rl_completion_type: c_int;
+        pub static mut rl_startup_hook: Option<HookFunction>;
+        pub static mut rl_pre_input_hook: Option<HookFunction>;
+        pub static mut rl_event_hook: Option<HookFunction>;
+        pub static mut rl_getc_function: Option<GetcFunction>;
+        pub static mut rl_catch_signals: c_int;
+        pub static mut rl_catch_sigwinch: c_int;
 
         pub fn using_history();
         pub fn add_history(line: *const c_char);
         pub fn history_get(offset: c_int) -> *mut HistEntry;
         pub fn clear_history();
-        //pub fn where_history() -> c_int;
+        pub fn history_set_pos(pos: c_int) -> c_int;
 
         pub fn read_history(filename: *const c_char) -> c_int;
         pub fn write_history(filename: *const c_char) -> c_int;
@@ -110,12 +196,66 @@ mod ffi {
         pub fn rl_callback_handler_install(prompt: *const c_char, handler: super::VCPFunction);
         pub fn rl_callback_read_char();
         pub fn rl_callback_handler_remove();
+        pub fn rl_set_keyboard_input_timeout(usecs: c_int) -> c_int;
+        pub fn rl_redisplay();
+        pub fn rl_set_prompt(prompt: *const c_char) -> c_int;
+        pub fn rl_add_defun(name: *const c_char, function: Option<CommandFunction>, key: c_int) -> c_int;
+        pub fn rl_insert_text(text: *const c_char) -> c_int;
+        pub fn rl_newline(count: c_int, key: c_int) -> c_int;
+        pub fn rl_ding() -> c_int;
+        pub fn rl_forced_update_display() -> c_int;
+        pub fn rl_bind_key(key: c_int, function: Option<CommandFunction>) -> c_int;
+        #[cfg(rl_clear_visible_line)]
+        pub fn rl_clear_visible_line() -> c_int;
+        #[cfg(bracketed_paste)]
+        pub fn rl_variable_bind(variable: *const c_char, value: *const c_char) -> c_int;
+        pub fn rl_get_screen_size(rows: *mut c_int, cols: *mut c_int);
+        pub fn rl_set_screen_size(rows: c_int, cols: c_int);
+        pub fn rl_resize_terminal();
+        pub fn rl_cleanup_after_signal();
+        pub fn rl_reset_after_signal();
+        pub fn rl_free_line_state();
 
         pub fn rl_completion_matches(text: *const c_char, entry_func: super::CompletionEntryFunction) -> *mut *const c_char;
+        pub fn rl_display_match_list(matches: *mut *mut c_char, len: c_int, max: c_int);
+        pub fn rl_filename_completion_function(text: *const c_char, state: c_int) -> *const c_char;
     }
-    extern {
+    extern "C" {
         pub fn strdup(s: *const c_char) -> *const c_char;
     }
+
+    // not provided by libedit
+    #[cfg(not(any(feature = "libedit", feature = "pure-rust", feature = "dynamic")))]
+    extern "C" {
+        pub static mut rl_filename_quoting_desired: c_int;
+        pub static mut rl_filename_rewrite_hook: Option<DequoteFunction>;
+        pub static mut rl_completion_suppress_quote: c_int;
+        pub static mut rl_char_is_quoted_p: Option<LineBufFunction>;
+        pub static mut rl_filename_quote_characters: *const c_char;
+        pub static mut rl_filename_quoting_function: Option<QuoteFunction>;
+        pub static mut rl_filename_dequoting_function: Option<DequoteFunction>;
+        pub static mut rl_signal_event_hook: Option<HookFunction>;
+
+        pub fn rl_variable_value(variable: *const c_char) -> *const c_char;
+        pub fn rl_bind_keyseq(keyseq: *const c_char, function: Option<CommandFunction>) -> c_int;
+        pub fn rl_forward_char(count: c_int, key: c_int) -> c_int;
+        pub fn rl_function_of_keyseq(keyseq: *const c_char, map: Keymap, typ: *mut c_int) -> Option<CommandFunction>;
+        pub fn rl_free_undo_list();
+    }
+
+    // provided by libedit, but not following the position set by `history_set_pos`
+    #[cfg(not(any(feature = "libedit", feature = "pure-rust", feature = "dynamic")))]
+    extern "C" {
+        pub fn where_history() -> c_int;
+        pub fn current_history() -> *mut HistEntry;
+        pub fn history_search_prefix(string: *const c_char, direction: c_int) -> c_int;
+    }
+    #[cfg(feature = "libedit")]
+    pub use super::libedit::*;
+    #[cfg(feature = "pure-rust")]
+    pub use super::pure::*;
+    #[cfg(feature = "dynamic")]
+    pub use super::dynamic::*;
 }
 
 /// Begin a session in which the history functions might be used. This initializes the interactive variables.
@@ -125,18 +265,18 @@ pub fn using_history() {
     unsafe { ffi::using_history() }
 }
 
-//static mut PREV_HIST: *const i8 = 0 as *const i8;
+//static mut PREV_HIST: *const c_char = 0 as *const c_char;
 
 /// Place `line` at the end of the history list.
 ///
 /// Blank lines and consecutive duplicates are discarded.
 /// (See [add_history](http://cnswww.cns.cwru.edu/php/chet/readline/history.html#IDX5))
 pub fn add_history(line: &str) {
-    if line.len() == 0 || line.chars().next().map_or(true, |c| c.is_whitespace()) { // HISTCONTROL=ignorespace
+    if line.is_empty() || line.starts_with(char::is_whitespace) { // HISTCONTROL=ignorespace
         return;
     }
     // HISTCONTROL=ignoredups
-    if history_get(-1).map_or(false, |prev| prev == line) {
+    if history_get(-1).is_some_and(|prev| prev == line) {
         return;
     }
     let c_line = CString::new(line).unwrap();
@@ -300,9 +440,14 @@ pub fn history_length() -> i32 {
 ///
 /// If readline encounters an EOF while reading the line, and the line is empty at that point, then `None` is returned.
 /// Otherwise, the line is ended just as if a newline had been typed.
+/// When lines are not read interactively (see `Mode`), the line is read from stdin without editing.
 /// (See [readline](http://cnswww.cns.cwru.edu/php/chet/readline/readline.html#IDX190))
 pub fn readline(prompt: &str) -> Option<String> {
+    if !plain::is_interactive() {
+        return plain::readline(prompt);
+    }
     let c_prompt = CString::new(prompt).unwrap();
+    display::reset();
     let c_line = unsafe { ffi::readline(c_prompt.as_ptr()) };
     if c_line.is_null() {  // user pressed Ctrl-D
         None
@@ -316,7 +461,7 @@ pub fn readline(prompt: &str) -> Option<String> {
 /// Return the line gathered so far.
 ///
 /// (See [rl_line_buffer](http://cnswww.cns.cwru.edu/php/chet/readline/readline.html#IDX192))
-pub fn rl_line_buffer() -> *mut i8 {
+pub fn rl_line_buffer() -> *mut c_char {
     unsafe { ffi::rl_line_buffer }
 }
 
@@ -342,15 +487,49 @@ pub fn rl_initialize() -> Result<()> {
 ///
 /// (See [rl_library_version](http://cnswww.cns.cwru.edu/php/chet/readline/readline.html#IDX214))
 pub fn rl_library_version() -> String {
+    // the library is loaded by the first call
+    #[cfg(feature = "dynamic")]
+    available();
     let slice = unsafe { CStr::from_ptr(ffi::rl_library_version).to_bytes() };
     str::from_utf8(slice).unwrap().to_string()
 }
 
+/// Say if libreadline (or libedit) was found at runtime.
+///
+/// When it is missing, `readline` reads lines from stdin without editing, and there is no history.
+/// Only available with the `dynamic` feature.
+#[cfg(feature = "dynamic")]
+pub fn available() -> bool {
+    ffi::available()
+}
+
 /// Returns an integer encoding the current version of the library.
 ///
 /// (See [rl_readline_version](http://cnswww.cns.cwru.edu/php/chet/readline/readline.html#IDX214))
 pub fn rl_readline_version() -> i32 {
-    ffi::rl_readline_version
+    #[cfg(feature = "dynamic")]
+    available();
+    unsafe { ffi::rl_readline_version }
+}
+
+/// Clear the screen lines occupied by the prompt and the line being edited, leaving the cursor at the start of the first one.
+///
+/// Only available with readline 7.0 and later.
+/// (See [rl_clear_visible_line](http://cnswww.cns.cwru.edu/php/chet/readline/readline.html))
+#[cfg(rl_clear_visible_line)]
+pub fn rl_clear_visible_line() {
+    unsafe { ffi::rl_clear_visible_line() };
+}
+
+/// Make readline insert pasted text as a single string instead of reading it as typed keys
+/// (so that pasted newlines or TABs do not accept the line or complete).
+///
+/// Only available with readline 7.0 and later (the `enable-bracketed-paste` variable).
+#[cfg(bracketed_paste)]
+pub fn set_enable_bracketed_paste(b: bool) {
+    let variable = CString::new("enable-bracketed-paste").unwrap();
+    let value = CString::new(if b { "on" } else { "off" }).unwrap();
+    unsafe { ffi::rl_variable_bind(variable.as_ptr(), value.as_ptr()) };
 }
 
 /// Name is set to a unique name by each application using Readline. The value allows conditional parsing of the inputrc file.
@@ -423,12 +602,190 @@ pub fn set_rl_completer_word_break_characters(wbc: &str) {
     unsafe { ffi::rl_completer_word_break_characters = ffi::strdup(c_wbc.as_ptr()) };
 }
 
+/// Return the character appended after a single completion match (`None` if nothing is appended).
+///
+/// (See [rl_completion_append_character](http://cnswww.cns.cwru.edu/php/chet/readline/readline.html))
+pub fn rl_completion_append_character() -> Option<char> {
+    let c = unsafe { ffi::rl_completion_append_character };
+    if c == 0 { None } else { std::char::from_u32(c as u32) }
+}
+
+/// Set the character appended after a single completion match (`None` to append nothing).
+/// It is reset to a space before each completion, so it should be set by the completion function.
+///
+/// # Panics
+///
+/// If `c` is not ASCII: readline appends a single byte.
+///
+/// (See [rl_completion_append_character](http://cnswww.cns.cwru.edu/php/chet/readline/readline.html))
+pub fn set_rl_completion_append_character(c: Option<char>) {
+    let c = c.map_or(0, |c| {
+        assert!(c.is_ascii(), "the append character must be ASCII");
+        c as i32
+    });
+    unsafe { ffi::rl_completion_append_character = c; }
+}
+
+/// Say if the append character is not appended after a completion match.
+///
+/// (See [rl_completion_suppress_append](http://cnswww.cns.cwru.edu/php/chet/readline/readline.html))
+pub fn rl_completion_suppress_append() -> bool {
+    unsafe { ffi::rl_completion_suppress_append != 0 }
+}
+
+/// Prevent the append character from being appended after a completion match.
+/// It is reset before each completion, so it should be set by the completion function.
+///
+/// (See [rl_completion_suppress_append](http://cnswww.cns.cwru.edu/php/chet/readline/readline.html))
+pub fn set_rl_completion_suppress_append(b: bool) {
+    unsafe { ffi::rl_completion_suppress_append = b as i32; }
+}
+
+/// Say if the closing quote is not appended after a quoted completion match.
+///
+/// (See [rl_completion_suppress_quote](http://cnswww.cns.cwru.edu/php/chet/readline/readline.html))
+pub fn rl_completion_suppress_quote() -> bool {
+    unsafe { ffi::rl_completion_suppress_quote != 0 }
+}
+
+/// Prevent the closing quote from being appended after a quoted completion match.
+/// It is reset before each completion, so it should be set by the completion function.
+///
+/// (See [rl_completion_suppress_quote](http://cnswww.cns.cwru.edu/php/chet/readline/readline.html))
+pub fn set_rl_completion_suppress_quote(b: bool) {
+    unsafe { ffi::rl_completion_suppress_quote = b as i32; }
+}
+
+/// Return the characters which are word break characters but should be left in the word being completed (e.g. "$@").
+///
+/// (See [rl_special_prefixes](http://cnswww.cns.cwru.edu/php/chet/readline/readline.html))
+pub fn rl_special_prefixes() -> Option<String> {
+    let prefixes = unsafe { ffi::rl_special_prefixes };
+    c_str_to_string(prefixes)
+}
+
+/// Set the characters which are word break characters but should be left in the word being completed.
+///
+/// (See [rl_special_prefixes](http://cnswww.cns.cwru.edu/php/chet/readline/readline.html))
+pub fn set_rl_special_prefixes(prefixes: &str) {
+    // The memory will never be freed.
+    let c_prefixes = CString::new(prefixes).unwrap();
+    unsafe { ffi::rl_special_prefixes = ffi::strdup(c_prefixes.as_ptr()) };
+}
+
+/// Return the characters which can be used to quote a substring of the line, so that word break characters are ignored in it.
+///
+/// (See [rl_completer_quote_characters](http://cnswww.cns.cwru.edu/php/chet/readline/readline.html))
+pub fn rl_completer_quote_characters() -> Option<String> {
+    let qc = unsafe { ffi::rl_completer_quote_characters };
+    c_str_to_string(qc)
+}
+
+/// Set the characters which can be used to quote a substring of the line (e.g. "\"'").
+///
+/// (See [rl_completer_quote_characters](http://cnswww.cns.cwru.edu/php/chet/readline/readline.html))
+pub fn set_rl_completer_quote_characters(qc: &str) {
+    // The memory will never be freed.
+    let c_qc = CString::new(qc).unwrap();
+    unsafe { ffi::rl_completer_quote_characters = ffi::strdup(c_qc.as_ptr()) };
+}
+
+/// Return the quote characters readline uses to split words.
+/// The default list is "\"'".
+///
+/// (See [rl_basic_quote_characters](http://cnswww.cns.cwru.edu/php/chet/readline/readline.html))
+pub fn rl_basic_quote_characters() -> Option<String> {
+    let qc = unsafe { ffi::rl_basic_quote_characters };
+    c_str_to_string(qc)
+}
+
+/// Set the quote characters readline uses to split words.
+///
+/// (See [rl_basic_quote_characters](http://cnswww.cns.cwru.edu/php/chet/readline/readline.html))
+pub fn set_rl_basic_quote_characters(qc: &str) {
+    // The memory will never be freed.
+    let c_qc = CString::new(qc).unwrap();
+    unsafe { ffi::rl_basic_quote_characters = ffi::strdup(c_qc.as_ptr()) };
+}
+
+/// Return the characters which cause a filename to be quoted when they appear in a completed filename.
+///
+/// (See [rl_filename_quote_characters](http://cnswww.cns.cwru.edu/php/chet/readline/readline.html))
+pub fn rl_filename_quote_characters() -> Option<String> {
+    let qc = unsafe { ffi::rl_filename_quote_characters };
+    c_str_to_string(qc)
+}
+
+/// Set the characters which cause a filename to be quoted when they appear in a completed filename.
+///
+/// (See [rl_filename_quote_characters](http://cnswww.cns.cwru.edu/php/chet/readline/readline.html))
+pub fn set_rl_filename_quote_characters(qc: &str) {
+    // The memory will never be freed.
+    let c_qc = CString::new(qc).unwrap();
+    unsafe { ffi::rl_filename_quote_characters = ffi::strdup(c_qc.as_ptr()) };
+}
+
+/// Return the number of completions above which the user is asked whether the list should be displayed.
+/// A negative value means the user is never asked.
+///
+/// (See [rl_completion_query_items](http://cnswww.cns.cwru.edu/php/chet/readline/readline.html))
+pub fn rl_completion_query_items() -> i32 {
+    unsafe { ffi::rl_completion_query_items }
+}
+
+/// Set the number of completions above which the user is asked whether the list should be displayed.
+///
+/// (See [rl_completion_query_items](http://cnswww.cns.cwru.edu/php/chet/readline/readline.html))
+pub fn set_rl_completion_query_items(n: i32) {
+    unsafe { ffi::rl_completion_query_items = n; }
+}
+
+/// Say if the completion matches are sorted before being displayed.
+///
+/// (See [rl_sort_completion_matches](http://cnswww.cns.cwru.edu/php/chet/readline/readline.html))
+pub fn rl_sort_completion_matches() -> bool {
+    unsafe { ffi::rl_sort_completion_matches != 0 }
+}
+
+/// Set whether the completion matches are sorted before being displayed, the default is true.
+///
+/// (See [rl_sort_completion_matches](http://cnswww.cns.cwru.edu/php/chet/readline/readline.html))
+pub fn set_rl_sort_completion_matches(b: bool) {
+    unsafe { ffi::rl_sort_completion_matches = b as i32; }
+}
+
+/// Say if duplicate completion matches are removed.
+///
+/// (See [rl_ignore_completion_duplicates](http://cnswww.cns.cwru.edu/php/chet/readline/readline.html))
+pub fn rl_ignore_completion_duplicates() -> bool {
+    unsafe { ffi::rl_ignore_completion_duplicates != 0 }
+}
+
+/// Set whether duplicate completion matches are removed, the default is true.
+///
+/// (See [rl_ignore_completion_duplicates](http://cnswww.cns.cwru.edu/php/chet/readline/readline.html))
+pub fn set_rl_ignore_completion_duplicates(b: bool) {
+    unsafe { ffi::rl_ignore_completion_duplicates = b as i32; }
+}
+
+/// Return the type of completion readline is currently attempting:
+/// '\t' for normal completion, '?' for listing, '*' for inserting all, '!' or '@' for listing
+/// or completing depending on the number of matches, '%' for menu completion.
+/// It is set by readline before calling the completion function and cannot be changed.
+///
+/// (See [rl_completion_type](http://cnswww.cns.cwru.edu/php/chet/readline/readline.html))
+pub fn rl_completion_type() -> char {
+    let t = unsafe { ffi::rl_completion_type };
+    std::char::from_u32(t as u32).unwrap_or('\t')
+}
+
 pub fn set_rl_attempted_completion_function(f: CPPFunction) {
     unsafe { ffi::rl_attempted_completion_function = f }
 }
 
 pub fn rl_callback_handler_install(prompt: &str, handler: VCPFunction) {
     let c_prompt = CString::new(prompt).unwrap();
+    display::reset();
     unsafe {
         ffi::rl_callback_handler_install(c_prompt.as_ptr(), handler)
     }
@@ -446,25 +803,25 @@ pub fn rl_callback_handler_remove() {
     }
 }
 
-pub fn rl_completion_matches(text: *const i8, entry_func: CompletionEntryFunction) -> *mut *const i8 {
+// `text` is given by readline to the attempted completion function
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub fn rl_completion_matches(text: *const c_char, entry_func: CompletionEntryFunction) -> *mut *const c_char {
     unsafe {
         ffi::rl_completion_matches(text, entry_func)
     }
 }
 
-fn c_str_to_string(c_str: *const i8) -> Option<String> {
+/// Copy `c_str`, replacing invalid UTF-8: it is often called from readline's callbacks, where a panic aborts.
+fn c_str_to_string(c_str: *const c_char) -> Option<String> {
     if c_str.is_null() {
         None
     } else {
-        let c_slice = unsafe { CStr::from_ptr(c_str).to_bytes() };
-        Some(str::from_utf8(c_slice).unwrap().to_string())
+        Some(unsafe { CStr::from_ptr(c_str) }.to_string_lossy().into_owned())
     }
 }
 
 #[cfg(test)]
 mod history_tests {
-    extern crate tempdir;
-
     use std::path::Path;
 
     #[test]
@@ -521,11 +878,27 @@ mod history_tests {
         super::clear_history();
     }
 
+    #[test]
+    // libedit keeps the base
+    #[cfg(not(feature = "libedit"))]
+    fn clear_history_resets_base() {
+        super::rl_initialize().unwrap();
+        super::clear_history();
+        super::stifle_history(1);
+        super::add_history("entry1");
+        super::add_history("entry2");
+        assert_eq!(super::history_base(), 2);
+        super::unstifle_history();
+
+        super::clear_history();
+        assert_eq!(super::history_base(), 1);
+    }
+
     #[test]
     fn read_history() {
         super::rl_initialize().unwrap();
         super::clear_history();
-        let td = tempdir::TempDir::new_in(&Path::new("."), "histo").unwrap();
+        let td = tempdir::TempDir::new_in(Path::new("."), "histo").unwrap();
         let history = td.path().join(".history");
 
         super::add_history("entry1");
@@ -557,6 +930,19 @@ mod rl_tests {
         super::rl_parse_and_bind("bind \\t rl_complete").unwrap();
     }
 
+    #[test]
+    #[cfg(bracketed_paste)]
+    fn enable_bracketed_paste() {
+        use std::ffi::CString;
+        let variable = CString::new("enable-bracketed-paste").unwrap();
+        super::set_enable_bracketed_paste(false);
+        let value = super::c_str_to_string(unsafe { super::ffi::rl_variable_value(variable.as_ptr()) });
+        assert_eq!(value, Some("off".to_string()));
+        super::set_enable_bracketed_paste(true);
+        let value = super::c_str_to_string(unsafe { super::ffi::rl_variable_value(variable.as_ptr()) });
+        assert_eq!(value, Some("on".to_string()));
+    }
+
     #[test]
     fn rl_readline_name() {
         //assert_eq!(super::rl_readline_name(), Some("".to_string()));
@@ -570,4 +956,49 @@ mod rl_tests {
         super::set_rl_completer_word_break_characters(" \t\n\"\\'`@$><=;|&{(");
         assert_eq!(super::rl_completer_word_break_characters(), Some(" \t\n\"\\'`@$><=;|&{(".to_string()));
     }
+
+    #[test]
+    fn rl_completion_append_character() {
+        super::set_rl_completion_append_character(Some('='));
+        assert_eq!(super::rl_completion_append_character(), Some('='));
+        super::set_rl_completion_append_character(None);
+        assert_eq!(super::rl_completion_append_character(), None);
+        super::set_rl_completion_append_character(Some(' '));
+    }
+
+    #[test]
+    #[should_panic(expected = "the append character must be ASCII")]
+    fn rl_completion_append_character_non_ascii() {
+        super::set_rl_completion_append_character(Some('é'));
+    }
+
+    #[test]
+    fn rl_completer_quote_characters() {
+        super::set_rl_completer_quote_characters("\"'");
+        assert_eq!(super::rl_completer_quote_characters(), Some("\"'".to_string()));
+        super::set_rl_special_prefixes("$@");
+        assert_eq!(super::rl_special_prefixes(), Some("$@".to_string()));
+    }
+
+    #[test]
+    fn rl_sort_completion_matches() {
+        // libedit does not sort by default
+        #[cfg(not(feature = "libedit"))]
+        assert!(super::rl_sort_completion_matches(), "matches are expected to be sorted by default");
+        super::set_rl_sort_completion_matches(false);
+        assert!(!super::rl_sort_completion_matches(), "matches sorting has not been disabled");
+        super::set_rl_sort_completion_matches(true);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn get_compentry() {
+        super::set_compentries(vec!["alpha".to_string(), "al\0pine".to_string()]);
+        let entry = super::get_compentry(0);
+        assert_eq!(super::c_str_to_string(entry), Some("alpha".to_string()));
+        unsafe { libc::free(entry as *mut libc::c_void) };
+        // an interior NUL
+        assert!(super::get_compentry(1).is_null());
+        assert!(super::get_compentry(2).is_null());
+    }
 }
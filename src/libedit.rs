@@ -0,0 +1,77 @@
+//! Stand-ins for the parts of the readline API missing from libedit, or not working as in readline.
+//!
+//! Variables are plain statics which libedit never reads, so setting them has no effect,
+//! and functions report a failure when they can.
+
+#![allow(non_upper_case_globals)]
+
+use std::ffi::CStr;
+use std::ptr;
+use libc::{c_char, c_int};
+
+use super::ffi::{self, CommandFunction, DequoteFunction, HistEntry, HookFunction, Keymap, LineBufFunction, QuoteFunction};
+
+extern "C" {
+    // the position set by `history_set_pos`
+    static history_offset: c_int;
+}
+
+pub static mut rl_filename_quoting_desired: c_int = 1;
+pub static mut rl_filename_rewrite_hook: Option<DequoteFunction> = None;
+pub static mut rl_completion_suppress_quote: c_int = 0;
+pub static mut rl_char_is_quoted_p: Option<LineBufFunction> = None;
+pub static mut rl_filename_quote_characters: *const c_char = ptr::null();
+pub static mut rl_filename_quoting_function: Option<QuoteFunction> = None;
+pub static mut rl_filename_dequoting_function: Option<DequoteFunction> = None;
+pub static mut rl_signal_event_hook: Option<HookFunction> = None;
+
+/// No variable can be read back: `NULL`.
+pub unsafe fn rl_variable_value(_variable: *const c_char) -> *const c_char {
+    ptr::null()
+}
+
+/// libedit only binds single keys to functions registered with `rl_add_defun`: fails.
+pub unsafe fn rl_bind_keyseq(_keyseq: *const c_char, _function: Option<CommandFunction>) -> c_int {
+    -1
+}
+
+/// Does nothing: the cursor position cannot be changed from a bound function.
+pub unsafe fn rl_forward_char(_count: c_int, _key: c_int) -> c_int {
+    0
+}
+
+/// The bindings are unknown: `None`.
+pub unsafe fn rl_function_of_keyseq(_keyseq: *const c_char, _map: Keymap, _typ: *mut c_int) -> Option<CommandFunction> {
+    None
+}
+
+/// Does nothing: libedit's undo buffer cannot be freed.
+pub unsafe fn rl_free_undo_list() {}
+
+/// libedit's `where_history` reports the position of its own cursor, which `history_set_pos` does not move:
+/// the position set by `history_set_pos`.
+pub unsafe fn where_history() -> c_int {
+    history_offset
+}
+
+/// libedit's `current_history` finds some entries only: the entry at the position set by `history_set_pos`.
+pub unsafe fn current_history() -> *mut HistEntry {
+    ffi::history_get(ffi::history_base + history_offset)
+}
+
+/// libedit's `history_search_prefix` searches from its own cursor and leaves the position unchanged:
+/// the entries are compared from the position set by `history_set_pos`, which is moved to the match.
+pub unsafe fn history_search_prefix(string: *const c_char, direction: c_int) -> c_int {
+    let prefix = CStr::from_ptr(string).to_bytes();
+    let step = if direction < 0 { -1 } else { 1 };
+    let mut pos = if direction < 0 { history_offset.min(ffi::history_length - 1) } else { history_offset };
+    while pos >= 0 && pos < ffi::history_length {
+        let entry = ffi::history_get(ffi::history_base + pos);
+        if !entry.is_null() && CStr::from_ptr((*entry).line).to_bytes().starts_with(prefix) {
+            ffi::history_set_pos(pos);
+            return 0;
+        }
+        pos += step;
+    }
+    -1
+}
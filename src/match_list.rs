@@ -0,0 +1,179 @@
+//! The matches handed to readline by an attempted completion function.
+
+#[cfg(test)]
+use std::cell::Cell;
+use std::ffi::{CStr, CString};
+use std::mem;
+use std::ptr;
+use libc::{c_char, c_void};
+
+/// The matches of one completion.
+///
+/// Readline expects a `NULL` terminated `malloc`'d array of `malloc`'d strings,
+/// whose first element replaces the word being completed (the single match, or the text common to all the matches),
+/// and frees it once the completion is done.
+/// A `MatchList` owns its matches until it is converted to this array by `into_raw`,
+/// so nothing is shared between completions.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MatchList {
+    replacement: String,
+    matches: Vec<String>,
+}
+
+impl MatchList {
+    /// Create the list of `matches`, `replacement` replacing the word being completed when there are several of them.
+    pub fn new(replacement: &str, matches: Vec<String>) -> MatchList {
+        MatchList { replacement: replacement.to_string(), matches }
+    }
+
+    /// Return the text replacing the word being completed.
+    pub fn replacement(&self) -> &str {
+        if self.matches.len() == 1 { &self.matches[0] } else { &self.replacement }
+    }
+
+    /// Return the matches.
+    pub fn matches(&self) -> &[String] {
+        &self.matches
+    }
+
+    /// Return the number of matches.
+    pub fn len(&self) -> usize {
+        self.matches.len()
+    }
+
+    /// Say if there is no match.
+    pub fn is_empty(&self) -> bool {
+        self.matches.is_empty()
+    }
+
+    /// Convert the list to the array returned by an attempted completion function (`NULL` when there is no match).
+    ///
+    /// Readline takes ownership of the array; otherwise, it must be given back to `from_raw`.
+    pub fn into_raw(self) -> *mut *mut c_char {
+        if self.matches.is_empty() {
+            return ptr::null_mut();
+        }
+        let mut strings = Vec::with_capacity(self.matches.len() + 1);
+        if self.matches.len() > 1 {
+            strings.push(self.replacement);
+        }
+        strings.extend(self.matches);
+        unsafe {
+            let array = alloc((strings.len() + 1) * mem::size_of::<*mut c_char>()) as *mut *mut c_char;
+            for (i, s) in strings.iter().enumerate() {
+                *array.add(i) = strdup(s);
+            }
+            *array.add(strings.len()) = ptr::null_mut();
+            array
+        }
+    }
+
+    /// Take back the ownership of an array of matches created by `into_raw` (or by `rl_completion_matches`),
+    /// freeing it.
+    ///
+    /// # Safety
+    ///
+    /// `array` must be `NULL` or a `NULL` terminated `malloc`'d array of `malloc`'d strings, not used afterwards.
+    pub unsafe fn from_raw(array: *mut *mut c_char) -> MatchList {
+        let mut strings = Vec::new();
+        if !array.is_null() {
+            let mut i = 0;
+            loop {
+                let s = *array.add(i);
+                if s.is_null() {
+                    break;
+                }
+                strings.push(CStr::from_ptr(s).to_string_lossy().into_owned());
+                free(s as *mut c_void);
+                i += 1;
+            }
+            free(array as *mut c_void);
+        }
+        match strings.len() {
+            0 => MatchList::new("", strings),
+            1 => {
+                let replacement = strings[0].clone();
+                MatchList::new(&replacement, strings)
+            }
+            _ => {
+                let replacement = strings.remove(0);
+                MatchList::new(&replacement, strings)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+thread_local! {
+    // blocks allocated and not freed yet
+    static ALLOCATIONS: Cell<isize> = const { Cell::new(0) };
+}
+
+unsafe fn alloc(size: usize) -> *mut c_void {
+    let p = libc::malloc(size);
+    if p.is_null() {
+        panic!("Memory allocation failed.");
+    }
+    #[cfg(test)]
+    ALLOCATIONS.with(|a| a.set(a.get() + 1));
+    p
+}
+
+unsafe fn free(p: *mut c_void) {
+    #[cfg(test)]
+    ALLOCATIONS.with(|a| a.set(a.get() - 1));
+    libc::free(p);
+}
+
+unsafe fn strdup(s: &str) -> *mut c_char {
+    let c_s = CString::new(s).unwrap();
+    let bytes = c_s.as_bytes_with_nul();
+    let p = alloc(bytes.len()) as *mut c_char;
+    ptr::copy_nonoverlapping(bytes.as_ptr() as *const c_char, p, bytes.len());
+    p
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CStr;
+
+    use super::{ALLOCATIONS, MatchList};
+
+    fn strings(matches: &[&str]) -> Vec<String> {
+        matches.iter().map(|m| m.to_string()).collect()
+    }
+
+    #[test]
+    fn into_raw() {
+        let raw = MatchList::new("se", strings(&["select", "set"])).into_raw();
+        unsafe {
+            let raw: Vec<String> = (0..3).map(|i| CStr::from_ptr(*raw.add(i)).to_str().unwrap().to_string()).collect();
+            assert_eq!(raw, vec!["se", "select", "set"]);
+        }
+        assert!(unsafe { (*raw.add(3)).is_null() });
+        let list = unsafe { MatchList::from_raw(raw) };
+        assert_eq!(list.replacement(), "se");
+        assert_eq!(list.matches(), &strings(&["select", "set"])[..]);
+
+        // a single match replaces the word
+        let list = unsafe { MatchList::from_raw(MatchList::new("s", strings(&["select"])).into_raw()) };
+        assert_eq!(list.replacement(), "select");
+        assert_eq!(list.len(), 1);
+
+        assert!(MatchList::new("x", Vec::new()).into_raw().is_null());
+    }
+
+    #[test]
+    fn no_leak() {
+        for n in 0..4 {
+            let matches: Vec<String> = (0..n).map(|i| format!("match{}", i)).collect();
+            let raw = MatchList::new("match", matches.clone()).into_raw();
+            // the array, the matches and the replacement when there are several matches
+            let expected = match n { 0 => 0, 1 => 2, n => n + 2 };
+            assert_eq!(ALLOCATIONS.with(|a| a.get()), expected as isize);
+            let list = unsafe { MatchList::from_raw(raw) };
+            assert_eq!(list.matches(), &matches[..]);
+            assert_eq!(ALLOCATIONS.with(|a| a.get()), 0);
+        }
+    }
+}
@@ -0,0 +1,156 @@
+//! Matching the word being completed against candidates.
+
+use std::ffi::CString;
+
+use super::ffi;
+
+/// How candidates are matched against the word being completed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Matcher {
+    /// Candidates starting with the word.
+    Prefix,
+    /// Candidates starting with the word, ignoring case.
+    IgnoreCase,
+    /// Candidates containing the word, ignoring case.
+    Substring,
+    /// Candidates containing the characters of the word in order, ignoring case.
+    /// Consecutive characters and characters at the start of words rank higher.
+    Fuzzy,
+}
+
+impl Default for Matcher {
+    /// `IgnoreCase` if the readline variable `completion-ignore-case` is on, `Prefix` otherwise.
+    fn default() -> Matcher {
+        if completion_ignore_case() { Matcher::IgnoreCase } else { Matcher::Prefix }
+    }
+}
+
+impl Matcher {
+    /// Return the score of `candidate` (the higher, the better), or `None` if it does not match `text`.
+    pub fn score(&self, text: &str, candidate: &str) -> Option<i32> {
+        match *self {
+            Matcher::Prefix => if candidate.starts_with(text) { Some(0) } else { None },
+            Matcher::IgnoreCase => if starts_with_ignore_case(candidate, text) { Some(0) } else { None },
+            Matcher::Substring => {
+                let pos = candidate.to_lowercase().find(&text.to_lowercase())?;
+                // earlier and tighter matches first
+                Some(-(pos as i32) * 2 - candidate.len().saturating_sub(text.len()) as i32)
+            }
+            Matcher::Fuzzy => fuzzy_score(text, candidate),
+        }
+    }
+
+    /// Return the `candidates` matching `text`, the best ones first (alphabetically for equal scores).
+    ///
+    /// Readline sorts the matches before displaying them unless `set_rl_sort_completion_matches(false)` is called.
+    pub fn filter<I>(&self, text: &str, candidates: I) -> Vec<String> where I: IntoIterator<Item = String> {
+        let mut scored: Vec<(i32, String)> = candidates.into_iter()
+            .filter_map(|candidate| self.score(text, &candidate).map(|score| (score, candidate)))
+            .collect();
+        scored.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+        scored.into_iter().map(|(_, candidate)| candidate).collect()
+    }
+}
+
+/// Say if the readline variable `completion-ignore-case` is on.
+pub fn completion_ignore_case() -> bool {
+    let name = CString::new("completion-ignore-case").unwrap();
+    let value = unsafe { ffi::rl_variable_value(name.as_ptr()) };
+    super::c_str_to_string(value).is_some_and(|value| value == "on")
+}
+
+fn starts_with_ignore_case(s: &str, prefix: &str) -> bool {
+    let mut chars = s.chars().flat_map(char::to_lowercase);
+    prefix.chars().flat_map(char::to_lowercase).all(|p| chars.next() == Some(p))
+}
+
+fn fuzzy_score(text: &str, candidate: &str) -> Option<i32> {
+    let mut score = 0;
+    let mut chars = candidate.chars().enumerate();
+    let mut before: Option<char> = None;
+    let mut prev: Option<usize> = None;
+    for t in text.chars().flat_map(char::to_lowercase) {
+        loop {
+            let (i, c) = chars.next()?;
+            let word_start = before.is_none_or(|b| !b.is_alphanumeric());
+            before = Some(c);
+            if c.to_lowercase().eq(Some(t)) {
+                score += match prev {
+                    Some(j) if j + 1 == i => 5, // consecutive
+                    Some(j) => 1 - (i - j - 1).min(5) as i32,
+                    None => 1 - i.min(5) as i32,
+                };
+                if word_start {
+                    score += 3;
+                }
+                prev = Some(i);
+                break;
+            }
+        }
+    }
+    Some(score)
+}
+
+/// Return the text replacing `text` when several `matches` are found (`matches[0]` for readline).
+///
+/// This is the longest common prefix of the matches (ignoring case if `ignore_case`),
+/// unless it does not extend `text`, as with substring or fuzzy matches; then `text` is kept.
+pub fn common_prefix(text: &str, matches: &[String], ignore_case: bool) -> String {
+    if matches.len() == 1 {
+        return matches[0].clone();
+    }
+    let first = match matches.first() {
+        Some(first) => first,
+        None => return text.to_string(),
+    };
+    let same = |a: char, b: char| if ignore_case { a.to_lowercase().eq(b.to_lowercase()) } else { a == b };
+    let mut len = first.len();
+    for m in &matches[1..] {
+        len = first.char_indices().zip(m.chars())
+            .take_while(|&((_, a), b)| same(a, b))
+            .last().map_or(0, |((i, a), _)| i + a.len_utf8())
+            .min(len);
+    }
+    let prefix = &first[..len];
+    let extends = if ignore_case { starts_with_ignore_case(prefix, text) } else { prefix.starts_with(text) };
+    if extends { prefix.to_string() } else { text.to_string() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Matcher;
+
+    fn candidates() -> Vec<String> {
+        vec!["select", "SELECT_ALL", "delete", "set_level", "show_tables"].into_iter().map(String::from).collect()
+    }
+
+    #[test]
+    fn prefix() {
+        assert_eq!(Matcher::Prefix.filter("se", candidates()), vec!["select", "set_level"]);
+        assert_eq!(Matcher::IgnoreCase.filter("se", candidates()), vec!["SELECT_ALL", "select", "set_level"]);
+    }
+
+    #[test]
+    fn substring() {
+        assert_eq!(Matcher::Substring.filter("le", candidates()), vec!["delete", "select", "SELECT_ALL", "set_level", "show_tables"]);
+    }
+
+    #[test]
+    fn fuzzy() {
+        assert_eq!(Matcher::Fuzzy.filter("sl", candidates()), vec!["set_level", "SELECT_ALL", "select", "show_tables"]);
+        assert_eq!(Matcher::Fuzzy.filter("stb", candidates()), vec!["show_tables"]);
+        assert!(Matcher::Fuzzy.filter("xyz", candidates()).is_empty());
+    }
+
+    #[test]
+    fn common_prefix() {
+        let matches = vec!["select".to_string(), "SELECT_ALL".to_string()];
+        assert_eq!(super::common_prefix("se", &matches, false), "se");
+        assert_eq!(super::common_prefix("se", &matches, true), "select");
+        let matches = vec!["set_level".to_string(), "select".to_string()];
+        assert_eq!(super::common_prefix("s", &matches, false), "se");
+        // fuzzy matches: the text is kept
+        assert_eq!(super::common_prefix("sl", &matches, false), "sl");
+        assert_eq!(super::common_prefix("stb", &["show_tables".to_string()], false), "show_tables");
+    }
+}
@@ -0,0 +1,151 @@
+//! Reading statements spanning several lines.
+
+use std::cell::{Cell, RefCell};
+use std::ffi::CString;
+use std::ptr;
+use libc::c_int;
+
+use super::display;
+use super::ffi;
+use super::plain;
+
+/// Outcome of the validation of the input.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ValidationResult {
+    /// The input is complete and is returned.
+    Valid,
+    /// The input is not complete: RETURN starts a new line.
+    Incomplete,
+    /// The input is rejected, with an optional message displayed below it: RETURN rings the bell.
+    Invalid(Option<String>),
+}
+
+/// Decide whether RETURN accepts the input.
+pub trait Validator {
+    /// Validate `input`, all the lines entered so far separated by '\n'.
+    fn validate(&self, input: &str) -> ValidationResult;
+}
+
+impl<F> Validator for F where F: Fn(&str) -> ValidationResult {
+    fn validate(&self, input: &str) -> ValidationResult {
+        self(input)
+    }
+}
+
+thread_local! {
+    static VALIDATOR: RefCell<Option<Box<dyn Validator>>> = const { RefCell::new(None) };
+    static CONTINUATION_PROMPT: RefCell<String> = RefCell::new("... ".to_string());
+    // lines accepted so far by `readline_multiline`, each one followed by '\n'
+    static BLOCK: RefCell<String> = const { RefCell::new(String::new()) };
+    static INCOMPLETE: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Set the `Validator` consulted by `readline_multiline` when RETURN is pressed.
+///
+/// Without validator, every input is valid.
+pub fn set_validator(validator: Option<Box<dyn Validator>>) {
+    VALIDATOR.with(|v| *v.borrow_mut() = validator);
+}
+
+/// Set the prompt displayed by `readline_multiline` for the lines following the first one.
+///
+/// The default is "... ".
+pub fn set_continuation_prompt(prompt: &str) {
+    CONTINUATION_PROMPT.with(|p| *p.borrow_mut() = prompt.to_string());
+}
+
+fn validate(input: &str) -> ValidationResult {
+    VALIDATOR.with(|v| match *v.borrow() {
+        Some(ref v) => v.validate(input),
+        None => ValidationResult::Valid,
+    })
+}
+
+/// Replacement for `accept-line` bound to RETURN while `readline_multiline` is running.
+extern "C" fn accept_line(count: c_int, key: c_int) -> c_int {
+    let (line, _) = display::line_buffer();
+    let input = BLOCK.with(|block| format!("{}{}", block.borrow(), line));
+    match validate(&input) {
+        ValidationResult::Valid => unsafe { ffi::rl_newline(count, key) },
+        ValidationResult::Incomplete => {
+            INCOMPLETE.with(|incomplete| incomplete.set(true));
+            unsafe { ffi::rl_newline(count, key) }
+        }
+        ValidationResult::Invalid(msg) => {
+            unsafe { ffi::rl_ding() };
+            if let Some(msg) = msg {
+                display::write_out(&format!("\n{}\n", msg));
+                display::reset();
+                unsafe { ffi::rl_forced_update_display() };
+            }
+            0
+        }
+    }
+}
+
+/// Print a `prompt` and read lines until the `Validator` accepts them.
+///
+/// RETURN ends the input only if it is valid: when it is incomplete, another line is read
+/// with the continuation prompt. The lines are returned separated by '\n'.
+/// If EOF is encountered on the first line, `None` is returned; on a continuation line,
+/// the lines entered so far are returned.
+/// When lines are not read interactively (see `Mode`), the `Validator` is consulted after each line,
+/// and invalid input is returned as is.
+pub fn readline_multiline(prompt: &str) -> Option<String> {
+    let keys = [b'\r' as c_int, b'\n' as c_int];
+    let previous: Vec<_> = keys.iter().map(|&key| unsafe {
+        let seq = CString::new(vec![key as u8]).unwrap();
+        let f = ffi::rl_function_of_keyseq(seq.as_ptr(), ptr::null_mut(), ptr::null_mut());
+        ffi::rl_bind_key(key, Some(accept_line));
+        f
+    }).collect();
+
+    let mut block = String::new();
+    let mut prompt = prompt.to_string();
+    let result = loop {
+        BLOCK.with(|b| *b.borrow_mut() = block.clone());
+        INCOMPLETE.with(|incomplete| incomplete.set(false));
+        match super::readline(&prompt) {
+            Some(line) => block.push_str(&line),
+            None if block.is_empty() => break None,
+            None => break Some(block),
+        }
+        if !plain::is_interactive() {
+            INCOMPLETE.with(|incomplete| incomplete.set(validate(&block) == ValidationResult::Incomplete));
+        }
+        if !INCOMPLETE.with(|incomplete| incomplete.get()) {
+            break Some(block);
+        }
+        block.push('\n');
+        prompt = CONTINUATION_PROMPT.with(|p| p.borrow().clone());
+    };
+
+    BLOCK.with(|b| b.borrow_mut().clear());
+    for (&key, &f) in keys.iter().zip(previous.iter()) {
+        unsafe { ffi::rl_bind_key(key, f) };
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ValidationResult;
+
+    #[test]
+    fn validate() {
+        super::set_validator(None);
+        assert_eq!(super::validate("(1 +"), ValidationResult::Valid);
+
+        super::set_validator(Some(Box::new(|input: &str| {
+            if input.ends_with('\\') || input.matches('(').count() > input.matches(')').count() {
+                ValidationResult::Incomplete
+            } else {
+                ValidationResult::Valid
+            }
+        })));
+        assert_eq!(super::validate("(1 +"), ValidationResult::Incomplete);
+        assert_eq!(super::validate("(1 +\n2)"), ValidationResult::Valid);
+        assert_eq!(super::validate("echo \\"), ValidationResult::Incomplete);
+        super::set_validator(None);
+    }
+}
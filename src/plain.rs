@@ -0,0 +1,151 @@
+//! Reading lines without line editing when the input is not typed in a terminal.
+
+use std::cell::Cell;
+use std::io::{self, BufRead, Write};
+use libc::FILE;
+
+use super::display;
+use super::hooks;
+use super::session;
+
+/// How lines are read.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+    /// Lines are edited with readline when stdin and stdout (or the file descriptors of the
+    /// `ReadlineSession`) are terminals, or when the keys come from `set_rl_getc_function`,
+    /// and read plainly otherwise.
+    Auto,
+    /// Lines are always edited with readline.
+    ForceInteractive,
+    /// Lines are always read plainly: no terminal control, no completion, no redisplay.
+    ForcePlain,
+}
+
+thread_local! {
+    static MODE: Cell<Mode> = const { Cell::new(Mode::Auto) };
+    static SUPPRESS_PROMPT: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Set how `readline()`, `readline_hidden()` and `readline_multiline()` read lines.
+///
+/// The default is `Mode::Auto`, so that a program driven by piped input does not write terminal
+/// sequences into its output.
+pub fn set_mode(mode: Mode) {
+    MODE.with(|m| m.set(mode));
+}
+
+/// Say if lines are edited with readline, according to the `Mode`.
+pub fn is_interactive() -> bool {
+    match MODE.with(|m| m.get()) {
+        Mode::Auto if hooks::has_getc_function() => true,
+        Mode::Auto => {
+            let (input, output) = session::streams().map_or((0, 1), |(i, o)| unsafe { (libc::fileno(i), libc::fileno(o)) });
+            unsafe { libc::isatty(input) == 1 && libc::isatty(output) == 1 }
+        }
+        Mode::ForceInteractive => true,
+        Mode::ForcePlain => false,
+    }
+}
+
+/// Do not write the prompt when lines are read plainly.
+///
+/// By default, the prompt is written without its escape sequences.
+pub fn set_suppress_prompt(b: bool) {
+    SUPPRESS_PROMPT.with(|s| s.set(b));
+}
+
+/// Read the bytes of a line from `input`, without its line terminator, `None` at end of file.
+fn read_line<R: BufRead>(input: &mut R) -> Option<Vec<u8>> {
+    let mut line = Vec::new();
+    match input.read_until(b'\n', &mut line) {
+        Ok(0) | Err(_) => None,
+        Ok(_) => Some(without_terminator(line)),
+    }
+}
+
+/// Read a line from `stream` like `read_line`.
+fn read_stream_line(stream: *mut FILE) -> Option<Vec<u8>> {
+    let mut line = Vec::new();
+    loop {
+        match unsafe { libc::fgetc(stream) } {
+            libc::EOF if line.is_empty() => return None,
+            libc::EOF => break,
+            c => {
+                line.push(c as u8);
+                if c == b'\n' as i32 {
+                    break;
+                }
+            }
+        }
+    }
+    Some(without_terminator(line))
+}
+
+fn without_terminator(mut line: Vec<u8>) -> Vec<u8> {
+    if line.ends_with(b"\n") {
+        line.pop();
+        if line.ends_with(b"\r") {
+            line.pop();
+        }
+    }
+    line
+}
+
+/// Return `line` as a string, replacing invalid UTF-8 (rather than stopping at a line of another encoding).
+fn to_string(line: Vec<u8>) -> String {
+    String::from_utf8(line).unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned())
+}
+
+/// Write `prompt`, unless it is suppressed, and read a line from stdin or the input of the `ReadlineSession`.
+pub fn readline(prompt: &str) -> Option<String> {
+    readline_bytes(prompt).map(to_string)
+}
+
+/// Read a line like `readline`, returning its bytes as they were read.
+pub fn readline_bytes(prompt: &str) -> Option<Vec<u8>> {
+    let suppress_prompt = SUPPRESS_PROMPT.with(|s| s.get());
+    match session::streams() {
+        Some((input, _)) => {
+            if !suppress_prompt {
+                display::write_out(&display::visible(prompt));
+            }
+            read_stream_line(input)
+        }
+        None => {
+            if !suppress_prompt {
+                let mut stdout = io::stdout();
+                let _ = stdout.write_all(display::visible(prompt).as_bytes());
+                let _ = stdout.flush();
+            }
+            // stdin is buffered: what follows the line is kept for the next call
+            read_line(&mut io::stdin().lock())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use super::Mode;
+
+    #[test]
+    fn read_line() {
+        let read_line = |input: &mut Cursor<Vec<u8>>| super::read_line(input).map(super::to_string);
+        let mut input = Cursor::new(b"first\r\nsecond\n\xe9t\xe9\n\nlast".to_vec());
+        assert_eq!(read_line(&mut input), Some("first".to_string()));
+        assert_eq!(read_line(&mut input), Some("second".to_string()));
+        assert_eq!(read_line(&mut input), Some("\u{fffd}t\u{fffd}".to_string()));
+        assert_eq!(read_line(&mut input), Some(String::new()));
+        assert_eq!(read_line(&mut input), Some("last".to_string()));
+        assert_eq!(read_line(&mut input), None);
+    }
+
+    #[test]
+    fn is_interactive() {
+        super::set_mode(Mode::ForcePlain);
+        assert!(!super::is_interactive());
+        super::set_mode(Mode::ForceInteractive);
+        assert!(super::is_interactive());
+        super::set_mode(Mode::Auto);
+    }
+}
@@ -0,0 +1,83 @@
+//! Prompts with colors and other escape sequences.
+
+use std::ffi::CString;
+use std::fmt;
+
+use super::display;
+use super::ffi;
+
+/// A prompt whose escape sequences are enclosed in `RL_PROMPT_START_IGNORE`/`RL_PROMPT_END_IGNORE`
+/// so that readline does not count them when wrapping lines.
+///
+/// ```no_run
+/// let prompt = readline::Prompt::new().styled("db", "1;32").text(":").styled("main", "33").text("> ");
+/// readline::readline(prompt.as_str());
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Prompt {
+    prompt: String,
+}
+
+impl Prompt {
+    /// Create an empty prompt.
+    pub fn new() -> Prompt {
+        Prompt { prompt: String::new() }
+    }
+
+    /// Append `text`, which may contain escape sequences.
+    pub fn text(mut self, text: &str) -> Prompt {
+        self.prompt.push_str(&display::ignore_escape_sequences(text));
+        self
+    }
+
+    /// Append `text` displayed with the Select Graphic Rendition parameters `sgr` (for example "1;31" for bold red).
+    pub fn styled(mut self, text: &str, sgr: &str) -> Prompt {
+        self.prompt.push_str(&format!("\x01\x1b[{}m\x02", sgr));
+        self.prompt.push_str(&display::ignore_escape_sequences(text));
+        self.prompt.push_str("\x01\x1b[0m\x02");
+        self
+    }
+
+    /// Return the prompt to pass to `readline()`.
+    pub fn as_str(&self) -> &str {
+        &self.prompt
+    }
+}
+
+impl<'a> From<&'a str> for Prompt {
+    fn from(text: &'a str) -> Prompt {
+        Prompt::new().text(text)
+    }
+}
+
+impl fmt::Display for Prompt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.prompt)
+    }
+}
+
+/// Replace the prompt of the line being edited and redisplay it.
+///
+/// It may be called from a hook (see `set_rl_event_hook`) to update a clock or a status in the prompt.
+/// (See [rl_set_prompt](http://cnswww.cns.cwru.edu/php/chet/readline/readline.html))
+pub fn set_prompt(prompt: &str) {
+    let c_prompt = CString::new(prompt).unwrap();
+    unsafe {
+        ffi::rl_set_prompt(c_prompt.as_ptr());
+        if let Some(redisplay) = ffi::rl_redisplay_function {
+            redisplay();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Prompt;
+
+    #[test]
+    fn prompt() {
+        let prompt = Prompt::new().styled("db", "1;32").text("> ");
+        assert_eq!(prompt.as_str(), "\x01\x1b[1;32m\x02db\x01\x1b[0m\x02> ");
+        assert_eq!(Prompt::from("\x1b[33m$\x1b[0m ").to_string(), "\x01\x1b[33m\x02$\x01\x1b[0m\x02 ");
+    }
+}
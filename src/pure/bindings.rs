@@ -0,0 +1,343 @@
+//! Key bindings, named functions and variables, set by the program or by inputrc lines.
+
+use std::collections::HashMap;
+use std::env;
+use std::ffi::{CStr, CString};
+use std::fs;
+use std::path::PathBuf;
+use std::ptr;
+use std::sync::{Mutex, MutexGuard};
+use libc::{c_char, c_int};
+
+use super::super::ffi::{CommandFunction, Keymap};
+use super::editor;
+
+/// What a key sequence is bound to.
+pub(super) enum Lookup {
+    Function(CommandFunction),
+    /// The sequence starts a longer bound one.
+    Prefix,
+    Unbound,
+}
+
+struct Bindings {
+    keys: HashMap<Vec<u8>, CommandFunction>,
+    functions: HashMap<String, CommandFunction>,
+    variables: HashMap<String, CString>,
+}
+
+impl Bindings {
+    fn new() -> Bindings {
+        let mut bindings = Bindings { keys: HashMap::new(), functions: HashMap::new(), variables: HashMap::new() };
+        for &(name, function, keys) in editor::COMMANDS {
+            bindings.functions.insert(name.to_string(), function);
+            for key in keys {
+                bindings.keys.insert(key.to_vec(), function);
+            }
+        }
+        for &(name, value) in VARIABLES {
+            bindings.variables.insert(name.to_string(), CString::new(value).unwrap());
+        }
+        bindings
+    }
+}
+
+// the variables used and their default value
+const VARIABLES: &[(&str, &str)] = &[
+    ("bell-style", "audible"),
+    ("completion-ignore-case", "off"),
+    ("editing-mode", "emacs"),
+    ("mark-directories", "on"),
+    ("show-all-if-ambiguous", "off"),
+];
+
+static BINDINGS: Mutex<Option<Bindings>> = Mutex::new(None);
+
+fn bindings() -> MutexGuard<'static, Option<Bindings>> {
+    BINDINGS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn with<R, F>(f: F) -> R where F: FnOnce(&mut Bindings) -> R {
+    f(bindings().get_or_insert_with(Bindings::new))
+}
+
+/// Return what `seq` is bound to.
+pub(super) fn lookup(seq: &[u8]) -> Lookup {
+    with(|b| match b.keys.get(seq) {
+        Some(&f) => Lookup::Function(f),
+        None if b.keys.keys().any(|k| k.starts_with(seq)) => Lookup::Prefix,
+        None => Lookup::Unbound,
+    })
+}
+
+/// Return the value of the variable `name`.
+pub(super) fn variable(name: &str) -> Option<String> {
+    with(|b| b.variables.get(name).map(|v| v.to_string_lossy().into_owned()))
+}
+
+/// Say if the boolean variable `name` is on.
+pub(super) fn is_on(name: &str) -> bool {
+    variable(name).is_some_and(|v| v == "on")
+}
+
+fn bind(seq: Vec<u8>, function: Option<CommandFunction>) {
+    with(|b| match function {
+        Some(f) => b.keys.insert(seq, f),
+        None => b.keys.remove(&seq),
+    });
+}
+
+/// Translate the escapes of a key sequence (`\C-x`, `\M-x`, `\e`, `\t`, octal and hexadecimal codes...).
+pub(super) fn parse_keyseq(s: &[u8]) -> Vec<u8> {
+    let mut seq = Vec::new();
+    let mut i = 0;
+    while i < s.len() {
+        if s[i] != b'\\' || i + 1 == s.len() {
+            seq.push(s[i]);
+            i += 1;
+            continue;
+        }
+        let rest = &s[i + 1..];
+        if rest.starts_with(b"C-") && rest.len() > 2 {
+            seq.push(control(rest[2]));
+            i += 4;
+        } else if rest.starts_with(b"M-") && rest.len() > 2 {
+            seq.push(0x1b);
+            i += 3;
+        } else {
+            let (byte, len) = match rest[0] {
+                b'e' => (0x1b, 1),
+                b'a' => (0x07, 1),
+                b'b' => (0x08, 1),
+                b'd' => (0x7f, 1),
+                b'f' => (0x0c, 1),
+                b'n' => (b'\n', 1),
+                b'r' => (b'\r', 1),
+                b't' => (b'\t', 1),
+                b'v' => (0x0b, 1),
+                b'0'..=b'7' => {
+                    let len = rest.iter().take(3).take_while(|c| (b'0'..=b'7').contains(c)).count();
+                    (number(&rest[..len], 8), len)
+                }
+                b'x' => {
+                    let len = rest[1..].iter().take(2).take_while(|c| c.is_ascii_hexdigit()).count();
+                    if len == 0 { (b'x', 1) } else { (number(&rest[1..len + 1], 16), len + 1) }
+                }
+                c => (c, 1),
+            };
+            seq.push(byte);
+            i += 1 + len;
+        }
+    }
+    seq
+}
+
+fn control(c: u8) -> u8 {
+    if c == b'?' { 0x7f } else { c.to_ascii_uppercase() & 0x1f }
+}
+
+fn number(digits: &[u8], radix: u32) -> u8 {
+    u32::from_str_radix(std::str::from_utf8(digits).unwrap(), radix).unwrap_or(0) as u8
+}
+
+/// Translate a key name (`Control-a`, `C-a`, `M-f`, `TAB`, `Rubout`...).
+fn parse_key_name(name: &str) -> Option<Vec<u8>> {
+    let mut name = name;
+    let (mut ctrl, mut meta) = (false, false);
+    loop {
+        let lower = name.to_ascii_lowercase();
+        if lower.starts_with("control-") {
+            ctrl = true;
+            name = &name[8..];
+        } else if lower.starts_with("c-") {
+            ctrl = true;
+            name = &name[2..];
+        } else if lower.starts_with("meta-") {
+            meta = true;
+            name = &name[5..];
+        } else if lower.starts_with("m-") {
+            meta = true;
+            name = &name[2..];
+        } else {
+            break;
+        }
+    }
+    let key = match name.to_ascii_lowercase().as_str() {
+        "del" | "rubout" => 0x7f,
+        "esc" | "escape" => 0x1b,
+        "lfd" | "newline" => b'\n',
+        "ret" | "return" => b'\r',
+        "spc" | "space" => b' ',
+        "tab" => b'\t',
+        _ if name.len() == 1 => name.as_bytes()[0],
+        _ => return None,
+    };
+    let key = if ctrl { control(key) } else { key };
+    Some(if meta { vec![0x1b, key] } else { vec![key] })
+}
+
+/// Apply an inputrc line: `set variable value`, `"keyseq": function-name` or `key-name: function-name`.
+///
+/// Conditional constructs, macros and unknown functions are ignored.
+fn parse_and_bind(line: &str) {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') || line.starts_with('$') {
+        return;
+    }
+    if let Some(rest) = line.strip_prefix("set ") {
+        let mut words = rest.split_whitespace();
+        if let (Some(name), Some(value)) = (words.next(), words.next()) {
+            let value = match value.to_ascii_lowercase().as_str() {
+                "1" => "on".to_string(),
+                "0" => "off".to_string(),
+                v => v.to_string(),
+            };
+            with(|b| b.variables.insert(name.to_ascii_lowercase(), CString::new(value).unwrap()));
+        }
+        return;
+    }
+    let (seq, function) = if let Some(quoted) = line.strip_prefix('"') {
+        // the closing quote is not escaped
+        let mut end = None;
+        let mut escaped = false;
+        for (i, c) in quoted.char_indices() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => {
+                    end = Some(i);
+                    break;
+                }
+                _ => {}
+            }
+        }
+        let end = match end {
+            Some(end) => end,
+            None => return,
+        };
+        match quoted[end + 1..].trim_start().strip_prefix(':') {
+            Some(function) => (parse_keyseq(&quoted.as_bytes()[..end]), function),
+            None => return,
+        }
+    } else {
+        match line.split_once(':') {
+            Some((name, function)) => match parse_key_name(name.trim()) {
+                Some(seq) => (seq, function),
+                None => return,
+            },
+            None => return,
+        }
+    };
+    let function = function.trim();
+    if let Some(f) = with(|b| b.functions.get(function).copied()) {
+        bind(seq, Some(f));
+    }
+}
+
+pub unsafe fn rl_parse_and_bind(line: *const c_char) -> c_int {
+    parse_and_bind(&CStr::from_ptr(line).to_string_lossy());
+    0
+}
+
+pub unsafe fn rl_read_init_file(filename: *const c_char) -> c_int {
+    let path = if !filename.is_null() {
+        PathBuf::from(CStr::from_ptr(filename).to_string_lossy().into_owned())
+    } else if let Some(inputrc) = env::var_os("INPUTRC") {
+        PathBuf::from(inputrc)
+    } else {
+        env::var_os("HOME").map(PathBuf::from).unwrap_or_default().join(".inputrc")
+    };
+    match fs::read(path) {
+        Ok(content) => {
+            for line in String::from_utf8_lossy(&content).lines() {
+                parse_and_bind(line);
+            }
+            0
+        }
+        Err(e) => e.raw_os_error().unwrap_or(libc::EIO),
+    }
+}
+
+pub unsafe fn rl_variable_value(variable: *const c_char) -> *const c_char {
+    let name = CStr::from_ptr(variable).to_string_lossy().to_ascii_lowercase();
+    // the string lives until the variable is set again
+    with(|b| b.variables.get(&name).map_or(ptr::null(), |v| v.as_ptr()))
+}
+
+pub unsafe fn rl_variable_bind(variable: *const c_char, value: *const c_char) -> c_int {
+    let name = CStr::from_ptr(variable).to_string_lossy();
+    let value = CStr::from_ptr(value).to_string_lossy();
+    parse_and_bind(&format!("set {} {}", name, value));
+    0
+}
+
+pub unsafe fn rl_bind_key(key: c_int, function: Option<CommandFunction>) -> c_int {
+    if !(0..=255).contains(&key) {
+        return 1;
+    }
+    bind(vec![key as u8], function);
+    0
+}
+
+pub unsafe fn rl_bind_keyseq(keyseq: *const c_char, function: Option<CommandFunction>) -> c_int {
+    let seq = parse_keyseq(CStr::from_ptr(keyseq).to_bytes());
+    if seq.is_empty() {
+        return 1;
+    }
+    bind(seq, function);
+    0
+}
+
+pub unsafe fn rl_add_defun(name: *const c_char, function: Option<CommandFunction>, key: c_int) -> c_int {
+    if let Some(f) = function {
+        let name = CStr::from_ptr(name).to_string_lossy().into_owned();
+        with(|b| b.functions.insert(name, f));
+    }
+    if key >= 0 {
+        rl_bind_key(key, function);
+    }
+    0
+}
+
+pub unsafe fn rl_function_of_keyseq(keyseq: *const c_char, _map: Keymap, typ: *mut c_int) -> Option<CommandFunction> {
+    if !typ.is_null() {
+        // ISFUNC
+        *typ = 0;
+    }
+    match lookup(&parse_keyseq(CStr::from_ptr(keyseq).to_bytes())) {
+        Lookup::Function(f) => Some(f),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn parse_keyseq() {
+        assert_eq!(super::parse_keyseq(b"\\e[C"), b"\x1b[C");
+        assert_eq!(super::parse_keyseq(b"\\C-a\\M-f"), b"\x01\x1bf");
+        assert_eq!(super::parse_keyseq(b"\\t\\177\\x41\\\\"), b"\t\x7fA\\");
+        assert_eq!(super::parse_keyseq(b"\r"), b"\r");
+    }
+
+    #[test]
+    fn parse_key_name() {
+        assert_eq!(super::parse_key_name("Control-u"), Some(vec![0x15]));
+        assert_eq!(super::parse_key_name("M-DEL"), Some(vec![0x1b, 0x7f]));
+        assert_eq!(super::parse_key_name("TAB"), Some(vec![b'\t']));
+        assert_eq!(super::parse_key_name("Hyper-x"), None);
+    }
+
+    #[test]
+    fn parse_and_bind() {
+        super::parse_and_bind("set show-all-if-ambiguous On");
+        assert!(super::is_on("show-all-if-ambiguous"));
+        super::parse_and_bind("set show-all-if-ambiguous off");
+        assert!(!super::is_on("show-all-if-ambiguous"));
+
+        super::parse_and_bind("\"\\C-xe\": end-of-line");
+        assert!(matches!(super::lookup(b"\x18"), super::Lookup::Prefix));
+        assert!(matches!(super::lookup(b"\x18e"), super::Lookup::Function(_)));
+        assert!(matches!(super::lookup(b"\x18f"), super::Lookup::Unbound));
+    }
+}
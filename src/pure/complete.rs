@@ -0,0 +1,416 @@
+//! Completion: finding the word to complete, calling the completion functions and inserting or listing the matches.
+
+use std::cell::RefCell;
+use std::env;
+use std::ffi::{CStr, CString, OsStr};
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::path::PathBuf;
+use std::ptr;
+use libc::{c_char, c_int, c_void};
+
+use super::super::display;
+use super::super::ffi::{DequoteFunction, DirectoryHookFunction, DisplayMatchesFunction, LineBufFunction, QuoteFunction};
+use super::super::{CPPFunction, CompletionEntryFunction};
+use super::bindings;
+use super::editor::{self, rl_line_buffer};
+use super::terminal::Input;
+
+pub static mut rl_attempted_completion_function: CPPFunction = None;
+pub static mut rl_attempted_completion_over: c_int = 0;
+pub static mut rl_completer_word_break_characters: *const c_char = c" \t\n\"\\'`@$><=;|&{(".as_ptr();
+pub static mut rl_filename_completion_desired: c_int = 0;
+pub static mut rl_filename_quoting_desired: c_int = 1;
+pub static mut rl_directory_completion_hook: Option<DirectoryHookFunction> = None;
+pub static mut rl_filename_rewrite_hook: Option<DequoteFunction> = None;
+pub static mut rl_completion_display_matches_hook: Option<DisplayMatchesFunction> = None;
+pub static mut rl_completion_append_character: c_int = b' ' as c_int;
+pub static mut rl_completion_suppress_append: c_int = 0;
+pub static mut rl_completion_suppress_quote: c_int = 0;
+pub static mut rl_special_prefixes: *const c_char = ptr::null();
+pub static mut rl_completer_quote_characters: *const c_char = ptr::null();
+pub static mut rl_char_is_quoted_p: Option<LineBufFunction> = None;
+pub static mut rl_filename_quote_characters: *const c_char = ptr::null();
+pub static mut rl_filename_quoting_function: Option<QuoteFunction> = None;
+pub static mut rl_filename_dequoting_function: Option<DequoteFunction> = None;
+pub static mut rl_basic_quote_characters: *const c_char = c"\"'".as_ptr();
+pub static mut rl_completion_query_items: c_int = 100;
+pub static mut rl_sort_completion_matches: c_int = 1;
+pub static mut rl_ignore_completion_duplicates: c_int = 1;
+pub static mut rl_completion_type: c_int = 0;
+
+/// The bytes of a C string, empty if it is `NULL`.
+fn bytes<'a>(s: *const c_char) -> &'a [u8] {
+    if s.is_null() { &[] } else { unsafe { CStr::from_ptr(s).to_bytes() } }
+}
+
+/// Return the start of the word to complete in `line` (the line up to the point),
+/// and the quote opened before it if any.
+fn word_start(line: &[u8]) -> (usize, Option<u8>) {
+    let (breaks, quotes, specials) = unsafe {
+        (bytes(rl_completer_word_break_characters), bytes(rl_completer_quote_characters), bytes(rl_special_prefixes))
+    };
+    let is_quoted = |i: usize| match unsafe { rl_char_is_quoted_p } {
+        Some(f) if !unsafe { rl_line_buffer }.is_null() => f(unsafe { rl_line_buffer }, i as c_int) != 0,
+        _ => false,
+    };
+    let mut quote = None;
+    let mut start = 0;
+    let mut escaped = false;
+    for (i, &c) in line.iter().enumerate() {
+        if escaped {
+            escaped = false;
+        } else if c == b'\\' && !quotes.is_empty() && quote.map(|(q, _)| q) != Some(b'\'') {
+            escaped = true;
+        } else if let Some((q, _)) = quote {
+            if c == q {
+                quote = None;
+            }
+        } else if quotes.contains(&c) {
+            quote = Some((c, i + 1));
+        } else if breaks.contains(&c) && !is_quoted(i) {
+            start = if specials.contains(&c) { i } else { i + 1 };
+        }
+    }
+    match quote {
+        Some((q, start)) => (start, Some(q)),
+        None => (start, None),
+    }
+}
+
+/// Take the matches returned by a completion function, freeing them.
+unsafe fn take_matches(matches: *mut *const c_char) -> Vec<Vec<u8>> {
+    let mut list = Vec::new();
+    if matches.is_null() {
+        return list;
+    }
+    let mut i = 0;
+    while !(*matches.add(i)).is_null() {
+        list.push(bytes(*matches.add(i)).to_vec());
+        libc::free(*matches.add(i) as *mut c_void);
+        i += 1;
+    }
+    libc::free(matches as *mut c_void);
+    list
+}
+
+/// Expand a leading `~/`.
+fn expand_tilde(path: &[u8]) -> PathBuf {
+    match (path.strip_prefix(b"~"), env::var_os("HOME")) {
+        (Some(rest), Some(home)) if rest.is_empty() || rest[0] == b'/' => {
+            let mut expanded = home.as_bytes().to_vec();
+            expanded.extend_from_slice(rest);
+            PathBuf::from(OsStr::from_bytes(&expanded))
+        }
+        _ => PathBuf::from(OsStr::from_bytes(path)),
+    }
+}
+
+/// Say if the match `m` is a prefix of `s`, ignoring case if `completion-ignore-case` is on.
+fn starts_with(s: &[u8], prefix: &[u8], ignore_case: bool) -> bool {
+    s.len() >= prefix.len() && if ignore_case { s[..prefix.len()].eq_ignore_ascii_case(prefix) } else { s.starts_with(prefix) }
+}
+
+/// Length of the longest common prefix of `matches`, ending on a character boundary.
+fn common_prefix_len(matches: &[Vec<u8>], ignore_case: bool) -> usize {
+    let first = match matches.first() {
+        Some(first) => first,
+        None => return 0,
+    };
+    let mut len = matches.iter().skip(1).fold(first.len(), |len, m| {
+        first[..len].iter().zip(m.iter())
+            .take_while(|&(a, b)| if ignore_case { a.eq_ignore_ascii_case(b) } else { a == b })
+            .count()
+    });
+    while len > 0 && len < first.len() && first[len] & 0xc0 == 0x80 {
+        len -= 1;
+    }
+    len
+}
+
+/// Insert the match `m` in place of `start..end`, quoting it if needed.
+/// A single match is followed by the closing quote and the append character, or `/` for a directory.
+unsafe fn insert_match(m: &[u8], start: usize, end: usize, quote: Option<u8>, single: bool) {
+    let mut text = m.to_vec();
+    let mut start = start;
+    let mut quote = quote;
+    let quote_characters = bytes(rl_filename_quote_characters);
+    if rl_filename_completion_desired != 0 && rl_filename_quoting_desired != 0 && text.iter().any(|c| quote_characters.contains(c)) {
+        if let Some(f) = rl_filename_quoting_function {
+            let c_text = CString::new(text.clone()).unwrap();
+            let mut quote_char = quote.unwrap_or(0) as c_char;
+            let quoted = f(c_text.as_ptr() as *mut c_char, if single { 1 } else { 2 }, &mut quote_char);
+            if !quoted.is_null() {
+                text = bytes(quoted).to_vec();
+                libc::free(quoted as *mut c_void);
+            }
+            // the quoted text starts with its opening quote
+            match (quote, text.first()) {
+                (Some(q), Some(&first)) if first == q => start -= 1,
+                (None, Some(&first)) if first == b'\'' || first == b'"' => quote = Some(first),
+                _ => {}
+            }
+        }
+    }
+    editor::replace(start, end, &text);
+    if !single {
+        return;
+    }
+    if rl_filename_completion_desired != 0 && bindings::is_on("mark-directories") && expand_tilde(m).is_dir() {
+        if !text.ends_with(b"/") {
+            editor::insert(b"/");
+        }
+        return;
+    }
+    if let Some(q) = quote.filter(|_| rl_completion_suppress_quote == 0) {
+        editor::insert(&[q]);
+    }
+    if rl_completion_suppress_append == 0 && rl_completion_append_character > 0 {
+        if let Some(c) = char::from_u32(rl_completion_append_character as u32) {
+            editor::insert(c.to_string().as_bytes());
+        }
+    }
+}
+
+/// The part of a match displayed in a list: the last component of a file name.
+fn printable_part(m: &[u8], filename: bool) -> &[u8] {
+    if !filename {
+        return m;
+    }
+    let trimmed = m.strip_suffix(b"/").unwrap_or(m);
+    match trimmed.iter().rposition(|&c| c == b'/') {
+        Some(i) => &m[i + 1..],
+        None => m,
+    }
+}
+
+/// List `matches` below the line, asking first if there are many of them.
+unsafe fn display_matches(common: &[u8], matches: &[Vec<u8>]) {
+    let strings: Vec<CString> = Some(common).into_iter().chain(matches.iter().map(|m| &m[..]))
+        .map(|m| CString::new(m).unwrap())
+        .collect();
+    let mut pointers: Vec<*mut c_char> = strings.iter().map(|s| s.as_ptr() as *mut c_char).collect();
+    pointers.push(ptr::null_mut());
+    let filename = rl_filename_completion_desired != 0;
+    let max = matches.iter().map(|m| display::width(&String::from_utf8_lossy(printable_part(m, filename)))).max().unwrap_or(0);
+    if let Some(hook) = rl_completion_display_matches_hook {
+        hook(pointers.as_mut_ptr(), matches.len() as c_int, max as c_int);
+        return;
+    }
+    if rl_completion_query_items > 0 && matches.len() >= rl_completion_query_items as usize {
+        display::write_out(&format!("\nDisplay all {} possibilities? (y or n)", matches.len()));
+        let yes = loop {
+            match editor::read_input(None) {
+                Input::Byte(b'y') | Input::Byte(b'Y') | Input::Byte(b' ') => break true,
+                Input::Byte(b'n') | Input::Byte(b'N') | Input::Byte(0x7f) | Input::Eof => break false,
+                _ => {}
+            }
+        };
+        if !yes {
+            display::write_out("\n");
+            display::reset();
+            return;
+        }
+    }
+    rl_display_match_list(pointers.as_mut_ptr(), matches.len() as c_int, max as c_int);
+    display::reset();
+}
+
+/// The `complete` command.
+pub(super) extern "C" fn complete(_count: c_int, key: c_int) -> c_int {
+    unsafe {
+        if editor::rl_inhibit_completion != 0 {
+            editor::insert(&[key as u8]);
+            return 0;
+        }
+        let repeated = editor::repeated_complete();
+        let (line, point) = editor::line();
+        let (start, quote) = word_start(&line[..point]);
+        let text = CString::new(&line[start..point]).unwrap_or_default();
+
+        rl_attempted_completion_over = 0;
+        rl_filename_completion_desired = 0;
+        rl_filename_quoting_desired = 1;
+        rl_completion_append_character = b' ' as c_int;
+        rl_completion_suppress_append = 0;
+        rl_completion_suppress_quote = 0;
+        rl_completion_type = if repeated { b'?' } else { b'\t' } as c_int;
+        let mut matches = ptr::null_mut();
+        if let Some(f) = rl_attempted_completion_function {
+            matches = f(text.as_ptr(), start as c_int, point as c_int);
+        }
+        if matches.is_null() && rl_attempted_completion_over == 0 {
+            matches = rl_completion_matches(text.as_ptr(), super::super::rl_filename_completion_function);
+        }
+        let mut matches = take_matches(matches);
+        if matches.is_empty() {
+            editor::ding();
+            return 0;
+        }
+        let common = matches.remove(0);
+        if matches.is_empty() {
+            matches.push(common.clone());
+        }
+        if rl_ignore_completion_duplicates != 0 {
+            matches.sort();
+            matches.dedup();
+        }
+        if matches.len() == 1 {
+            insert_match(&matches[0], start, point, quote, true);
+            return 0;
+        }
+        if common.len() > point - start {
+            insert_match(&common, start, point, quote, false);
+        }
+        if repeated || bindings::is_on("show-all-if-ambiguous") {
+            display_matches(&common, &matches);
+        } else {
+            editor::ding();
+        }
+    }
+    0
+}
+
+pub unsafe fn rl_completion_matches(text: *const c_char, entry_func: CompletionEntryFunction) -> *mut *const c_char {
+    let mut matches = Vec::new();
+    loop {
+        let m = entry_func(text, matches.len() as c_int);
+        if m.is_null() {
+            break;
+        }
+        matches.push(m);
+    }
+    if matches.is_empty() {
+        return ptr::null_mut();
+    }
+    // the first entry is the common prefix of the matches, or the only match
+    let common = if matches.len() == 1 {
+        matches.remove(0)
+    } else {
+        let list: Vec<Vec<u8>> = matches.iter().map(|&m| bytes(m).to_vec()).collect();
+        let len = common_prefix_len(&list, bindings::is_on("completion-ignore-case"));
+        libc::strndup(matches[0], len)
+    };
+    let array = libc::malloc((matches.len() + 2) * std::mem::size_of::<*const c_char>()) as *mut *const c_char;
+    if array.is_null() {
+        panic!("Memory allocation failed.");
+    }
+    *array = common;
+    for (i, &m) in matches.iter().enumerate() {
+        *array.add(i + 1) = m;
+    }
+    *array.add(matches.len() + 1) = ptr::null();
+    array
+}
+
+pub unsafe fn rl_display_match_list(matches: *mut *mut c_char, len: c_int, max: c_int) {
+    let filename = rl_filename_completion_desired != 0;
+    let mut items: Vec<String> = (1..=len.max(0) as usize)
+        .map(|i| String::from_utf8_lossy(printable_part(bytes(*matches.add(i)), filename)).into_owned())
+        .collect();
+    if rl_sort_completion_matches != 0 {
+        items.sort();
+    }
+    let (mut rows, mut cols) = (0, 0);
+    editor::rl_get_screen_size(&mut rows, &mut cols);
+    let width = max.max(0) as usize + 2;
+    let per_line = (cols.max(1) as usize / width).max(1);
+    let lines = items.len().div_ceil(per_line);
+    let mut out = String::from("\n");
+    for line in 0..lines {
+        // the matches are sorted down the columns
+        let mut i = line;
+        while i < items.len() {
+            out.push_str(&items[i]);
+            if i + lines < items.len() {
+                out.push_str(&" ".repeat(width.saturating_sub(display::width(&items[i]))));
+            }
+            i += lines;
+        }
+        out.push('\n');
+    }
+    display::write_out(&out);
+}
+
+thread_local! {
+    static FILENAMES: RefCell<Vec<Vec<u8>>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Return the files whose path starts with `text`.
+unsafe fn filenames(text: &[u8]) -> Vec<Vec<u8>> {
+    let (dir, prefix) = match text.iter().rposition(|&c| c == b'/') {
+        Some(i) => (&text[..=i], &text[i + 1..]),
+        None => (&b""[..], text),
+    };
+    let mut dirname = dir.to_vec();
+    if let Some(hook) = rl_directory_completion_hook.filter(|_| !dirname.is_empty()) {
+        let mut c_dirname = libc::strdup(CString::new(dirname.clone()).unwrap().as_ptr());
+        hook(&mut c_dirname);
+        dirname = bytes(c_dirname).to_vec();
+        libc::free(c_dirname as *mut c_void);
+    }
+    let path = if dirname.is_empty() { PathBuf::from(".") } else { expand_tilde(&dirname) };
+    let ignore_case = bindings::is_on("completion-ignore-case");
+    let mut names = Vec::new();
+    for entry in fs::read_dir(path).into_iter().flatten().flatten() {
+        let mut name = entry.file_name().as_bytes().to_vec();
+        if let Some(hook) = rl_filename_rewrite_hook {
+            let c_name = CString::new(name.clone()).unwrap();
+            let rewritten = hook(c_name.as_ptr() as *mut c_char, name.len() as c_int);
+            if !rewritten.is_null() && !ptr::eq(rewritten, c_name.as_ptr()) {
+                name = bytes(rewritten).to_vec();
+                libc::free(rewritten as *mut c_void);
+            }
+        }
+        if starts_with(&name, prefix, ignore_case) {
+            names.push([&dirname[..], &name[..]].concat());
+        }
+    }
+    names
+}
+
+pub unsafe fn rl_filename_completion_function(text: *const c_char, state: c_int) -> *const c_char {
+    if state == 0 {
+        let names = filenames(bytes(text));
+        FILENAMES.with(|f| *f.borrow_mut() = names);
+    }
+    FILENAMES.with(|f| {
+        let mut names = f.borrow_mut();
+        match names.get(state as usize) {
+            Some(name) => libc::strdup(CString::new(name.clone()).unwrap().as_ptr()),
+            None => {
+                names.clear();
+                ptr::null()
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn word_start() {
+        assert_eq!(super::word_start(b"ls -l fo"), (6, None));
+        assert_eq!(super::word_start(b"a=b"), (2, None));
+        assert_eq!(super::word_start(b""), (0, None));
+    }
+
+    #[test]
+    fn common_prefix_len() {
+        let matches = vec![b"alpha".to_vec(), b"alpine".to_vec()];
+        assert_eq!(super::common_prefix_len(&matches, false), 3);
+        let matches = vec![b"Alpha".to_vec(), b"alpine".to_vec()];
+        assert_eq!(super::common_prefix_len(&matches, false), 0);
+        assert_eq!(super::common_prefix_len(&matches, true), 3);
+        let matches = vec!["é".as_bytes().to_vec(), "è".as_bytes().to_vec()];
+        assert_eq!(super::common_prefix_len(&matches, false), 0);
+    }
+
+    #[test]
+    fn printable_part() {
+        assert_eq!(super::printable_part(b"src/lib.rs", false), b"src/lib.rs");
+        assert_eq!(super::printable_part(b"src/lib.rs", true), b"lib.rs");
+        assert_eq!(super::printable_part(b"src/pure/", true), b"pure/");
+        assert_eq!(super::printable_part(b"lib.rs", true), b"lib.rs");
+    }
+}
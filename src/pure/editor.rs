@@ -0,0 +1,818 @@
+//! Reading a line: the line buffer, the editing commands, the prompt and the callback interface.
+
+use std::cell::RefCell;
+use std::env;
+use std::ffi::CStr;
+use std::ptr;
+use std::slice;
+use std::time::Duration;
+use libc::{c_char, c_int, c_void, FILE};
+
+use super::super::display;
+use super::super::ffi::{CommandFunction, GetcFunction, HookFunction, VoidFunction};
+use super::super::VCPFunction;
+use super::bindings::{self, Lookup};
+use super::complete;
+use super::history;
+use super::terminal::{self, Input, RawMode};
+
+pub static mut rl_line_buffer: *mut c_char = ptr::null_mut();
+pub static mut rl_point: c_int = 0;
+pub static mut rl_end: c_int = 0;
+pub static mut rl_done: c_int = 0;
+pub static mut rl_display_prompt: *mut c_char = ptr::null_mut();
+pub static mut rl_instream: *mut FILE = ptr::null_mut();
+pub static mut rl_outstream: *mut FILE = ptr::null_mut();
+pub static mut rl_redisplay_function: Option<VoidFunction> = Some(rl_redisplay);
+pub static mut rl_inhibit_completion: c_int = 0;
+pub static mut rl_library_version: *const c_char = c"8.0 (pure Rust)".as_ptr();
+pub static mut rl_readline_version: c_int = 0x0800;
+pub static mut rl_readline_name: *const c_char = c"other".as_ptr();
+pub static mut rl_startup_hook: Option<HookFunction> = None;
+pub static mut rl_pre_input_hook: Option<HookFunction> = None;
+pub static mut rl_event_hook: Option<HookFunction> = None;
+pub static mut rl_signal_event_hook: Option<HookFunction> = None;
+pub static mut rl_getc_function: Option<GetcFunction> = Some(rl_getc);
+pub static mut rl_catch_signals: c_int = 1;
+// the size of the terminal is read at each redisplay: there is no SIGWINCH handler
+pub static mut rl_catch_sigwinch: c_int = 1;
+
+/// The commands with their name and default keys.
+pub(super) const COMMANDS: &[(&str, CommandFunction, &[&[u8]])] = &[
+    ("accept-line", accept_line, &[b"\r", b"\n"]),
+    ("complete", complete::complete, &[b"\t"]),
+    ("beginning-of-line", beginning_of_line, &[b"\x01", b"\x1b[H", b"\x1bOH", b"\x1b[1~"]),
+    ("end-of-line", end_of_line, &[b"\x05", b"\x1b[F", b"\x1bOF", b"\x1b[4~"]),
+    ("forward-char", forward_char, &[b"\x06", b"\x1b[C", b"\x1bOC"]),
+    ("backward-char", backward_char, &[b"\x02", b"\x1b[D", b"\x1bOD"]),
+    ("forward-word", forward_word, &[b"\x1bf", b"\x1b[1;5C"]),
+    ("backward-word", backward_word, &[b"\x1bb", b"\x1b[1;5D"]),
+    ("backward-delete-char", backward_delete_char, &[b"\x7f", b"\x08"]),
+    ("delete-char", delete_char, &[b"\x04", b"\x1b[3~"]),
+    ("kill-line", kill_line, &[b"\x0b"]),
+    ("unix-line-discard", unix_line_discard, &[b"\x15"]),
+    ("unix-word-rubout", unix_word_rubout, &[b"\x17"]),
+    ("yank", yank, &[b"\x19"]),
+    ("transpose-chars", transpose_chars, &[b"\x14"]),
+    ("clear-screen", clear_screen, &[b"\x0c"]),
+    ("previous-history", previous_history, &[b"\x10", b"\x1b[A", b"\x1bOA"]),
+    ("next-history", next_history, &[b"\x0e", b"\x1b[B", b"\x1bOB"]),
+    ("abort", abort, &[b"\x07"]),
+    ("self-insert", self_insert, &[]),
+    ("interrupt", interrupt, &[b"\x03"]),
+    ("suspend", suspend, &[b"\x1a"]),
+];
+
+struct State {
+    raw: Option<RawMode>,
+    // bytes of a key sequence or of a UTF-8 character being read
+    pending: Vec<u8>,
+    // entry displayed when browsing the history, and the line edited before
+    history_index: Option<usize>,
+    saved_line: Vec<u8>,
+    killed: Vec<u8>,
+    // the last command was a completion, and the current one follows another completion
+    last_complete: bool,
+    repeated_complete: bool,
+    eof: bool,
+    keyboard_input_timeout: c_int,
+    handler: VCPFunction,
+    initialized: bool,
+    // set by `rl_set_screen_size`, until `rl_resize_terminal`
+    screen_size: Option<(c_int, c_int)>,
+    // the terminal was in raw mode before `rl_cleanup_after_signal`
+    raw_before_signal: bool,
+}
+
+thread_local! {
+    static STATE: RefCell<State> = const { RefCell::new(State {
+        raw: None,
+        pending: Vec::new(),
+        history_index: None,
+        saved_line: Vec::new(),
+        killed: Vec::new(),
+        last_complete: false,
+        repeated_complete: false,
+        eof: false,
+        keyboard_input_timeout: 100_000,
+        handler: None,
+        initialized: false,
+        screen_size: None,
+        raw_before_signal: false,
+    }) };
+}
+
+fn state<R, F>(f: F) -> R where F: FnOnce(&mut State) -> R {
+    STATE.with(|state| f(&mut state.borrow_mut()))
+}
+
+/// Return a copy of the line buffer and the point.
+pub(super) fn line() -> (Vec<u8>, usize) {
+    unsafe {
+        if rl_line_buffer.is_null() {
+            return (Vec::new(), 0);
+        }
+        let line = slice::from_raw_parts(rl_line_buffer as *const u8, rl_end.max(0) as usize).to_vec();
+        let point = (rl_point.max(0) as usize).min(line.len());
+        (line, point)
+    }
+}
+
+/// Replace the line buffer and the point.
+pub(super) fn set_line(line: &[u8], point: usize) {
+    unsafe {
+        let buffer = libc::realloc(rl_line_buffer as *mut c_void, line.len() + 1) as *mut c_char;
+        if buffer.is_null() {
+            panic!("Memory allocation failed.");
+        }
+        ptr::copy_nonoverlapping(line.as_ptr() as *const c_char, buffer, line.len());
+        *buffer.add(line.len()) = 0;
+        rl_line_buffer = buffer;
+        rl_end = line.len() as c_int;
+        rl_point = point.min(line.len()) as c_int;
+    }
+}
+
+/// Replace `start..end` of the line with `text`, moving the point after it.
+pub(super) fn replace(start: usize, end: usize, text: &[u8]) {
+    let (mut line, _) = line();
+    let end = end.min(line.len());
+    line.splice(start.min(end)..end, text.iter().cloned());
+    set_line(&line, start + text.len());
+}
+
+/// Insert `text` at the point.
+pub(super) fn insert(text: &[u8]) {
+    let (_, point) = line();
+    replace(point, point, text);
+}
+
+fn set_point(point: usize) {
+    unsafe { rl_point = point.min(rl_end.max(0) as usize) as c_int };
+}
+
+/// Ring the bell, unless `bell-style` is `none`.
+pub(super) fn ding() {
+    if bindings::variable("bell-style").is_none_or(|style| style != "none") {
+        display::write_out("\x07");
+    }
+}
+
+/// Say if the current completion follows another one, and remember that a completion was done.
+pub(super) fn repeated_complete() -> bool {
+    state(|s| {
+        s.last_complete = true;
+        s.repeated_complete
+    })
+}
+
+fn redisplay() {
+    if let Some(f) = unsafe { rl_redisplay_function } {
+        unsafe { f() };
+    }
+}
+
+/// Number of bytes of the UTF-8 character starting with `lead`.
+fn utf8_len(lead: u8) -> usize {
+    match lead {
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf7 => 4,
+        _ => 1,
+    }
+}
+
+/// Start of the character before `point`.
+fn previous_char(line: &[u8], point: usize) -> usize {
+    let mut i = point.saturating_sub(1);
+    while i > 0 && line[i] & 0xc0 == 0x80 {
+        i -= 1;
+    }
+    i
+}
+
+/// End of the character at `point`.
+fn next_char(line: &[u8], point: usize) -> usize {
+    let mut i = (point + 1).min(line.len());
+    while i < line.len() && line[i] & 0xc0 == 0x80 {
+        i += 1;
+    }
+    i
+}
+
+fn is_word(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b >= 0x80
+}
+
+fn word_end(line: &[u8], point: usize) -> usize {
+    let mut i = point;
+    while i < line.len() && !is_word(line[i]) {
+        i += 1;
+    }
+    while i < line.len() && is_word(line[i]) {
+        i += 1;
+    }
+    i
+}
+
+fn word_start(line: &[u8], point: usize) -> usize {
+    let mut i = point;
+    while i > 0 && !is_word(line[i - 1]) {
+        i -= 1;
+    }
+    while i > 0 && is_word(line[i - 1]) {
+        i -= 1;
+    }
+    i
+}
+
+/// Handle the byte `c` read from the keyboard.
+fn dispatch(c: u8) {
+    let pending = state(|s| {
+        s.pending.push(c);
+        s.pending.clone()
+    });
+    if pending[0] >= 0x80 {
+        // a UTF-8 character is inserted once complete
+        if pending.len() < utf8_len(pending[0]) {
+            return;
+        }
+        state(|s| {
+            s.pending.clear();
+            s.last_complete = false;
+        });
+        insert(&pending);
+        return;
+    }
+    let function = match bindings::lookup(&pending) {
+        Lookup::Prefix => return,
+        Lookup::Function(f) => Some(f),
+        // the parameters of an unknown CSI sequence, until its final byte
+        Lookup::Unbound if pending.starts_with(b"\x1b[") && (pending.len() == 2 || !(0x40..=0x7e).contains(&c)) => return,
+        Lookup::Unbound if pending.len() == 1 && c >= 0x20 && c != 0x7f => Some(self_insert as CommandFunction),
+        // other unbound sequences are ignored
+        Lookup::Unbound => None,
+    };
+    state(|s| {
+        s.pending.clear();
+        s.repeated_complete = s.last_complete;
+        s.last_complete = false;
+    });
+    if let Some(f) = function {
+        f(1, c as c_int);
+    }
+}
+
+/// The file descriptor of `rl_instream`, stdin if it is not set.
+pub(super) fn input_fd() -> c_int {
+    unsafe { if rl_instream.is_null() { 0 } else { libc::fileno(rl_instream) } }
+}
+
+/// `rl_getc_function` if it is not `rl_getc`.
+fn custom_getc() -> Option<GetcFunction> {
+    unsafe { rl_getc_function }.filter(|&f| f as *const () != rl_getc as *const ())
+}
+
+/// Read a byte with `rl_getc_function` if it was replaced, from `rl_instream` otherwise
+/// (then waiting at most `timeout` if any).
+pub(super) fn read_input(timeout: Option<Duration>) -> Input {
+    match custom_getc() {
+        Some(getc) => match unsafe { getc(rl_instream) } {
+            libc::EOF => Input::Eof,
+            c => Input::Byte(c as u8),
+        },
+        None => terminal::read_byte(input_fd(), timeout),
+    }
+}
+
+/// Read a byte, calling the event hooks while waiting. Return `None` at end of file.
+fn read_key() -> Option<u8> {
+    loop {
+        let event_hook = unsafe { rl_event_hook };
+        let timeout = event_hook.map(|_| Duration::from_micros(state(|s| s.keyboard_input_timeout).max(1) as u64));
+        match read_input(timeout) {
+            Input::Byte(c) => return Some(c),
+            Input::Eof => return None,
+            Input::Timeout => {
+                if let Some(hook) = event_hook {
+                    hook();
+                }
+            }
+            Input::Interrupted => {
+                if let Some(hook) = unsafe { rl_signal_event_hook } {
+                    hook();
+                }
+            }
+        }
+    }
+}
+
+/// Start editing a new empty line: call the hooks and display the prompt.
+fn start_line() {
+    set_line(b"", 0);
+    unsafe { rl_done = 0 };
+    state(|s| {
+        s.pending.clear();
+        s.history_index = None;
+        s.last_complete = false;
+        s.eof = false;
+    });
+    display::reset();
+    if let Some(hook) = unsafe { rl_startup_hook } {
+        hook();
+    }
+    redisplay();
+    if let Some(hook) = unsafe { rl_pre_input_hook } {
+        hook();
+        redisplay();
+    }
+}
+
+/// Read and handle a key. Return `true` when the line is done (accepted or end of file).
+fn read_char() -> bool {
+    match read_key() {
+        Some(c) => dispatch(c),
+        None => state(|s| s.eof = true),
+    }
+    let done = unsafe { rl_done != 0 } || state(|s| s.eof);
+    if !done {
+        redisplay();
+    }
+    done
+}
+
+/// Finish the line: return a `malloc`'d copy of it, or `NULL` at end of file.
+fn finish_line() -> *mut c_char {
+    if state(|s| s.eof) && unsafe { rl_done } == 0 {
+        return ptr::null_mut();
+    }
+    unsafe { rl_done = 1 };
+    redisplay();
+    display::write_out("\n");
+    let (line, _) = line();
+    unsafe {
+        let copy = libc::malloc(line.len() + 1) as *mut c_char;
+        ptr::copy_nonoverlapping(line.as_ptr() as *const c_char, copy, line.len());
+        *copy.add(line.len()) = 0;
+        copy
+    }
+}
+
+/// Read a line from an input which is not a terminal, without editing.
+fn read_plain_line() -> *mut c_char {
+    display::write_out(&display::strip_ignore_markers(&display::display_prompt()));
+    let mut line = Vec::new();
+    loop {
+        match read_input(None) {
+            Input::Byte(b'\n') => break,
+            Input::Byte(c) => line.push(c),
+            Input::Eof if line.is_empty() => return ptr::null_mut(),
+            Input::Eof => break,
+            Input::Timeout | Input::Interrupted => {}
+        }
+    }
+    set_line(&line, line.len());
+    unsafe { libc::strdup(rl_line_buffer) }
+}
+
+pub unsafe fn readline(prompt: *const c_char) -> *const c_char {
+    rl_initialize();
+    rl_set_prompt(prompt);
+    let raw = RawMode::enable(input_fd());
+    // the line is still edited when the keys come from `rl_getc_function`
+    if raw.is_none() && custom_getc().is_none() {
+        return read_plain_line();
+    }
+    state(|s| s.raw = raw);
+    start_line();
+    while !read_char() {}
+    let line = finish_line();
+    state(|s| s.raw = None);
+    line
+}
+
+pub unsafe fn rl_initialize() -> c_int {
+    if rl_outstream.is_null() {
+        rl_outstream = libc::fdopen(1, c"w".as_ptr());
+    }
+    if rl_line_buffer.is_null() {
+        set_line(b"", 0);
+    }
+    if !state(|s| std::mem::replace(&mut s.initialized, true)) {
+        bindings::rl_read_init_file(ptr::null());
+    }
+    0
+}
+
+pub unsafe fn rl_callback_handler_install(prompt: *const c_char, handler: VCPFunction) {
+    rl_initialize();
+    rl_set_prompt(prompt);
+    let raw = RawMode::enable(input_fd());
+    state(|s| {
+        s.handler = handler;
+        s.raw = raw;
+    });
+    start_line();
+}
+
+pub unsafe fn rl_callback_read_char() {
+    if !read_char() {
+        return;
+    }
+    let line = finish_line();
+    let handler = state(|s| {
+        s.raw = None;
+        s.handler
+    });
+    if let Some(handler) = handler {
+        handler(line);
+    }
+    // the handler may have removed itself
+    if state(|s| s.handler.is_some()) {
+        let raw = RawMode::enable(input_fd());
+        state(|s| s.raw = raw);
+        start_line();
+    }
+}
+
+pub unsafe fn rl_callback_handler_remove() {
+    state(|s| {
+        s.handler = None;
+        s.raw = None;
+    });
+}
+
+pub unsafe fn rl_set_keyboard_input_timeout(usecs: c_int) -> c_int {
+    state(|s| {
+        let previous = s.keyboard_input_timeout;
+        if usecs >= 0 {
+            s.keyboard_input_timeout = usecs;
+        }
+        previous
+    })
+}
+
+/// Read a byte from `stream`, or `EOF`.
+pub unsafe extern "C" fn rl_getc(stream: *mut FILE) -> c_int {
+    let fd = if stream.is_null() { 0 } else { libc::fileno(stream) };
+    loop {
+        match terminal::read_byte(fd, None) {
+            Input::Byte(c) => return c as c_int,
+            Input::Interrupted => {}
+            Input::Eof | Input::Timeout => return libc::EOF,
+        }
+    }
+}
+
+pub unsafe extern "C" fn rl_redisplay() {
+    let (line, point) = display::line_buffer();
+    display::draw(&display::display_prompt(), &line, &line[..point]);
+}
+
+pub unsafe fn rl_set_prompt(prompt: *const c_char) -> c_int {
+    libc::free(rl_display_prompt as *mut c_void);
+    rl_display_prompt = libc::strdup(if prompt.is_null() { c"".as_ptr() } else { prompt });
+    0
+}
+
+pub unsafe fn rl_insert_text(text: *const c_char) -> c_int {
+    let text = CStr::from_ptr(text).to_bytes();
+    insert(text);
+    text.len() as c_int
+}
+
+pub unsafe fn rl_newline(count: c_int, key: c_int) -> c_int {
+    accept_line(count, key)
+}
+
+pub unsafe fn rl_forward_char(count: c_int, key: c_int) -> c_int {
+    forward_char(count, key)
+}
+
+pub unsafe fn rl_ding() -> c_int {
+    ding();
+    0
+}
+
+pub unsafe fn rl_forced_update_display() -> c_int {
+    redisplay();
+    0
+}
+
+pub unsafe fn rl_clear_visible_line() -> c_int {
+    display::clear();
+    0
+}
+
+pub unsafe fn rl_get_screen_size(rows: *mut c_int, cols: *mut c_int) {
+    let fd = if rl_outstream.is_null() { 1 } else { libc::fileno(rl_outstream) };
+    let env = |name: &str| env::var(name).ok().and_then(|v| v.parse().ok());
+    let (r, c) = state(|s| s.screen_size).unwrap_or_else(|| match terminal::size(fd) {
+        Some((r, c)) => (r as c_int, c as c_int),
+        None => (env("LINES").unwrap_or(24), env("COLUMNS").unwrap_or(80)),
+    });
+    if !rows.is_null() {
+        *rows = r;
+    }
+    if !cols.is_null() {
+        *cols = c;
+    }
+}
+
+pub unsafe fn rl_set_screen_size(rows: c_int, cols: c_int) {
+    state(|s| s.screen_size = Some((rows, cols)));
+}
+
+/// Forget the size set with `rl_set_screen_size` and redisplay the line being edited.
+pub unsafe fn rl_resize_terminal() {
+    let editing = state(|s| {
+        s.screen_size = None;
+        s.raw.is_some()
+    });
+    if editing {
+        redisplay();
+    }
+}
+
+/// Restore the terminal.
+pub unsafe fn rl_cleanup_after_signal() {
+    let raw = state(|s| s.raw.take());
+    state(|s| s.raw_before_signal = raw.is_some());
+    drop(raw);
+}
+
+/// Go back to raw mode if the terminal was in raw mode before `rl_cleanup_after_signal`.
+pub unsafe fn rl_reset_after_signal() {
+    if state(|s| std::mem::take(&mut s.raw_before_signal)) {
+        let raw = RawMode::enable(input_fd());
+        state(|s| s.raw = raw);
+    }
+}
+
+/// Does nothing: there is no undo list.
+pub unsafe fn rl_free_undo_list() {}
+
+/// Forget the key sequence being read and the completion in progress.
+pub unsafe fn rl_free_line_state() {
+    state(|s| {
+        s.pending.clear();
+        s.last_complete = false;
+        s.repeated_complete = false;
+    });
+}
+
+extern "C" fn accept_line(_count: c_int, _key: c_int) -> c_int {
+    unsafe { rl_done = 1 };
+    0
+}
+
+extern "C" fn self_insert(count: c_int, key: c_int) -> c_int {
+    insert(&vec![key as u8; count.max(1) as usize]);
+    0
+}
+
+extern "C" fn beginning_of_line(_count: c_int, _key: c_int) -> c_int {
+    set_point(0);
+    0
+}
+
+extern "C" fn end_of_line(_count: c_int, _key: c_int) -> c_int {
+    set_point(usize::MAX);
+    0
+}
+
+extern "C" fn forward_char(count: c_int, _key: c_int) -> c_int {
+    let (line, mut point) = line();
+    for _ in 0..count.max(1) {
+        point = next_char(&line, point);
+    }
+    set_point(point);
+    0
+}
+
+extern "C" fn backward_char(count: c_int, _key: c_int) -> c_int {
+    let (line, mut point) = line();
+    for _ in 0..count.max(1) {
+        point = previous_char(&line, point);
+    }
+    set_point(point);
+    0
+}
+
+extern "C" fn forward_word(_count: c_int, _key: c_int) -> c_int {
+    let (line, point) = line();
+    set_point(word_end(&line, point));
+    0
+}
+
+extern "C" fn backward_word(_count: c_int, _key: c_int) -> c_int {
+    let (line, point) = line();
+    set_point(word_start(&line, point));
+    0
+}
+
+extern "C" fn backward_delete_char(_count: c_int, _key: c_int) -> c_int {
+    let (line, point) = line();
+    if point == 0 {
+        ding();
+    } else {
+        replace(previous_char(&line, point), point, b"");
+    }
+    0
+}
+
+extern "C" fn delete_char(_count: c_int, key: c_int) -> c_int {
+    let (line, point) = line();
+    if line.is_empty() && key == 0x04 {
+        // Control-D on an empty line
+        state(|s| s.eof = true);
+    } else if point == line.len() {
+        ding();
+    } else {
+        replace(point, next_char(&line, point), b"");
+        set_point(point);
+    }
+    0
+}
+
+/// Remove `start..end` from the line, keeping it for `yank`.
+fn kill(start: usize, end: usize) {
+    let (line, _) = line();
+    state(|s| s.killed = line[start..end].to_vec());
+    replace(start, end, b"");
+}
+
+extern "C" fn kill_line(_count: c_int, _key: c_int) -> c_int {
+    let (line, point) = line();
+    kill(point, line.len());
+    0
+}
+
+extern "C" fn unix_line_discard(_count: c_int, _key: c_int) -> c_int {
+    let (_, point) = line();
+    kill(0, point);
+    0
+}
+
+extern "C" fn unix_word_rubout(_count: c_int, _key: c_int) -> c_int {
+    let (line, point) = line();
+    let mut start = point;
+    while start > 0 && line[start - 1].is_ascii_whitespace() {
+        start -= 1;
+    }
+    while start > 0 && !line[start - 1].is_ascii_whitespace() {
+        start -= 1;
+    }
+    kill(start, point);
+    0
+}
+
+extern "C" fn yank(_count: c_int, _key: c_int) -> c_int {
+    insert(&state(|s| s.killed.clone()));
+    0
+}
+
+extern "C" fn transpose_chars(_count: c_int, _key: c_int) -> c_int {
+    let (line, point) = line();
+    if line.is_empty() || point == 0 {
+        ding();
+        return 0;
+    }
+    // at the end of the line, the last two characters are swapped
+    let point = if point == line.len() { previous_char(&line, point) } else { point };
+    if point == 0 {
+        ding();
+        return 0;
+    }
+    let (start, end) = (previous_char(&line, point), next_char(&line, point));
+    let swapped = [&line[point..end], &line[start..point]].concat();
+    replace(start, end, &swapped);
+    0
+}
+
+extern "C" fn clear_screen(_count: c_int, _key: c_int) -> c_int {
+    display::write_out("\x1b[H\x1b[2J");
+    display::reset();
+    0
+}
+
+/// Display the history entry `index`, or the line edited before browsing the history if `None`.
+fn show_history(index: Option<usize>) {
+    let line = match index {
+        Some(index) => history::line(index).unwrap_or_default(),
+        None => state(|s| s.saved_line.clone()),
+    };
+    state(|s| s.history_index = index);
+    set_line(&line, line.len());
+}
+
+extern "C" fn previous_history(count: c_int, _key: c_int) -> c_int {
+    let index = state(|s| s.history_index);
+    let current = index.unwrap_or_else(history::len);
+    if current == 0 {
+        ding();
+        return 0;
+    }
+    if index.is_none() {
+        let (line, _) = line();
+        state(|s| s.saved_line = line);
+    }
+    show_history(Some(current.saturating_sub(count.max(1) as usize)));
+    0
+}
+
+extern "C" fn next_history(count: c_int, _key: c_int) -> c_int {
+    match state(|s| s.history_index) {
+        None => ding(),
+        Some(index) => {
+            let next = index + count.max(1) as usize;
+            show_history(if next < history::len() { Some(next) } else { None });
+        }
+    }
+    0
+}
+
+extern "C" fn abort(_count: c_int, _key: c_int) -> c_int {
+    ding();
+    0
+}
+
+/// Send `signal` to the process. Unless `rl_catch_signals` is 0, the terminal is restored meanwhile.
+fn raise(signal: c_int) {
+    let catch = unsafe { rl_catch_signals != 0 };
+    if catch {
+        unsafe { rl_cleanup_after_signal() };
+    }
+    unsafe { libc::raise(signal) };
+    if catch {
+        unsafe { rl_reset_after_signal() };
+    }
+    if let Some(hook) = unsafe { rl_signal_event_hook } {
+        hook();
+    }
+}
+
+extern "C" fn interrupt(_count: c_int, _key: c_int) -> c_int {
+    // if the signal is handled, the edition goes on with a new line,
+    // unless it is not caught: the application handles it all
+    if unsafe { rl_catch_signals == 0 } {
+        raise(libc::SIGINT);
+        return 0;
+    }
+    set_point(usize::MAX);
+    redisplay();
+    display::write_out("^C\n");
+    display::reset();
+    raise(libc::SIGINT);
+    set_line(b"", 0);
+    state(|s| s.history_index = None);
+    0
+}
+
+extern "C" fn suspend(_count: c_int, _key: c_int) -> c_int {
+    display::write_out("\n");
+    display::reset();
+    raise(libc::SIGTSTP);
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::ffi;
+
+    fn keys(keys: &[u8]) -> (String, usize) {
+        super::set_line(b"", 0);
+        for &key in keys {
+            super::dispatch(key);
+        }
+        let (line, point) = super::line();
+        (String::from_utf8(line).unwrap(), point)
+    }
+
+    #[test]
+    fn dispatch() {
+        assert_eq!(keys(b"abc"), ("abc".to_string(), 3));
+        assert_eq!(keys(b"abc\x02\x02X"), ("aXbc".to_string(), 2));
+        assert_eq!(keys(b"abc\x1b[D\x1b[D\x7f"), ("bc".to_string(), 0));
+        assert_eq!(keys(b"ab cd\x1bb\x0b"), ("ab ".to_string(), 3));
+        assert_eq!(keys(b"ab cd\x17\x01\x19"), ("cdab ".to_string(), 2));
+        assert_eq!(keys("é\x02x".as_bytes()), ("xé".to_string(), 1));
+        // unknown escape sequences are dropped
+        assert_eq!(keys(b"a\x1b[1;3Pb"), ("ab".to_string(), 2));
+        assert_eq!(keys(b"ab\x14"), ("ba".to_string(), 2));
+
+        super::set_line(b"", 0);
+        super::dispatch(b'\r');
+        assert_eq!(unsafe { ffi::rl_done }, 1);
+        unsafe { ffi::rl_done = 0 };
+    }
+
+    #[test]
+    fn previous_char() {
+        let line = "aé".as_bytes();
+        assert_eq!(super::previous_char(line, 3), 1);
+        assert_eq!(super::next_char(line, 1), 3);
+        assert_eq!(super::word_start(b"ab  cd", 4), 0);
+        assert_eq!(super::word_end(b"ab  cd", 2), 6);
+    }
+}
@@ -0,0 +1,276 @@
+//! The history list, and its file in readline's format: one entry per line,
+//! optionally preceded by a `#<seconds>` timestamp line.
+
+use std::env;
+use std::ffi::{CStr, CString};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::PathBuf;
+use std::ptr;
+use std::sync::{Mutex, MutexGuard};
+use libc::{c_char, c_int};
+
+use super::super::ffi::HistEntry;
+
+pub static mut history_base: c_int = 1;
+pub static mut history_length: c_int = 0;
+
+struct Entry(Box<HistEntry>);
+
+impl Entry {
+    fn new(line: &[u8]) -> Entry {
+        // a NUL ends the line, as in C
+        let line = line.split(|&b| b == 0).next().unwrap_or_default();
+        let line = CString::new(line).unwrap().into_raw();
+        Entry(Box::new(HistEntry { line }))
+    }
+
+    fn line(&self) -> &[u8] {
+        unsafe { CStr::from_ptr(self.0.line).to_bytes() }
+    }
+}
+
+impl Drop for Entry {
+    fn drop(&mut self) {
+        unsafe { drop(CString::from_raw(self.0.line as *mut c_char)) };
+    }
+}
+
+struct History {
+    entries: Vec<Entry>,
+    max_entries: usize,
+    stifled: bool,
+    // the current position, `entries.len()` past the newest entry
+    offset: usize,
+}
+
+// the entries are only shared through `history_get`, like readline's
+unsafe impl Send for History {}
+
+static HISTORY: Mutex<History> = Mutex::new(History { entries: Vec::new(), max_entries: 0, stifled: false, offset: 0 });
+
+fn history() -> MutexGuard<'static, History> {
+    HISTORY.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+impl History {
+    fn add(&mut self, line: &[u8]) {
+        self.entries.push(Entry::new(line));
+        self.stifle();
+    }
+
+    /// Remove the oldest entries beyond the maximum, the base following the first entry.
+    fn stifle(&mut self) {
+        if self.stifled && self.entries.len() > self.max_entries {
+            let removed = self.entries.len() - self.max_entries;
+            self.entries.drain(..removed);
+            self.offset = self.offset.saturating_sub(removed);
+            unsafe { history_base += removed as c_int };
+        }
+        unsafe { history_length = self.entries.len() as c_int };
+    }
+}
+
+/// Return the number of entries.
+pub(super) fn len() -> usize {
+    history().entries.len()
+}
+
+/// Return the entry `index`, starting from 0 (not from `history_base`).
+pub(super) fn line(index: usize) -> Option<Vec<u8>> {
+    history().entries.get(index).map(|entry| entry.line().to_vec())
+}
+
+pub unsafe fn using_history() {
+    let mut history = history();
+    history.offset = history.entries.len();
+}
+
+pub unsafe fn add_history(line: *const c_char) {
+    history().add(CStr::from_ptr(line).to_bytes());
+}
+
+pub unsafe fn history_get(offset: c_int) -> *mut HistEntry {
+    let history = history();
+    let index = offset - history_base;
+    if index < 0 {
+        return ptr::null_mut();
+    }
+    match history.entries.get(index as usize) {
+        Some(entry) => &*entry.0 as *const HistEntry as *mut HistEntry,
+        None => ptr::null_mut(),
+    }
+}
+
+pub unsafe fn clear_history() {
+    let mut history = history();
+    history.entries.clear();
+    history.offset = 0;
+    history_base = 1;
+    history_length = 0;
+}
+
+pub unsafe fn where_history() -> c_int {
+    history().offset as c_int
+}
+
+pub unsafe fn history_set_pos(pos: c_int) -> c_int {
+    let mut history = history();
+    if pos < 0 || pos as usize > history.entries.len() {
+        return 0;
+    }
+    history.offset = pos as usize;
+    1
+}
+
+pub unsafe fn current_history() -> *mut HistEntry {
+    let history = history();
+    match history.entries.get(history.offset) {
+        Some(entry) => &*entry.0 as *const HistEntry as *mut HistEntry,
+        None => ptr::null_mut(),
+    }
+}
+
+/// Move to the first entry starting with `string`, from the current one backward (`direction < 0`) or forward,
+/// returning 0 if there is one, -1 otherwise.
+pub unsafe fn history_search_prefix(string: *const c_char, direction: c_int) -> c_int {
+    let prefix = CStr::from_ptr(string).to_bytes();
+    let mut history = history();
+    let matches = |entry: &Entry| entry.line().starts_with(prefix);
+    let found = if direction < 0 {
+        // past the newest entry, the search starts from it
+        let start = history.offset.min(history.entries.len().saturating_sub(1));
+        history.entries.iter().take(start + 1).rposition(matches)
+    } else {
+        history.entries.iter().skip(history.offset).position(matches).map(|i| history.offset + i)
+    };
+    match found {
+        Some(index) => {
+            history.offset = index;
+            0
+        }
+        None => -1,
+    }
+}
+
+pub unsafe fn stifle_history(max: c_int) {
+    let mut history = history();
+    history.max_entries = max.max(0) as usize;
+    history.stifled = true;
+    history.stifle();
+}
+
+pub unsafe fn unstifle_history() -> c_int {
+    let mut history = history();
+    if history.stifled {
+        history.stifled = false;
+        history.max_entries as c_int
+    } else {
+        -(history.max_entries as c_int)
+    }
+}
+
+pub unsafe fn history_is_stifled() -> c_int {
+    history().stifled as c_int
+}
+
+/// `filename`, or `~/.history` if it is `NULL`.
+unsafe fn history_filename(filename: *const c_char) -> PathBuf {
+    if filename.is_null() {
+        env::var_os("HOME").map(PathBuf::from).unwrap_or_default().join(".history")
+    } else {
+        PathBuf::from(CStr::from_ptr(filename).to_string_lossy().into_owned())
+    }
+}
+
+fn errno(result: io::Result<()>) -> c_int {
+    match result {
+        Ok(()) => 0,
+        Err(e) => e.raw_os_error().unwrap_or(libc::EIO),
+    }
+}
+
+/// Say if `line` is a timestamp written before an entry.
+fn is_timestamp(line: &[u8]) -> bool {
+    line.len() > 1 && line[0] == b'#' && line[1..].iter().all(u8::is_ascii_digit)
+}
+
+/// Return the lines of `content`, without the final newline.
+fn lines(content: &[u8]) -> Vec<&[u8]> {
+    let content = content.strip_suffix(b"\n").unwrap_or(content);
+    if content.is_empty() { Vec::new() } else { content.split(|&b| b == b'\n').collect() }
+}
+
+pub unsafe fn read_history(filename: *const c_char) -> c_int {
+    errno(fs::read(history_filename(filename)).map(|content| {
+        let mut history = history();
+        for line in lines(&content).into_iter().filter(|line| !is_timestamp(line)) {
+            history.add(line);
+        }
+    }))
+}
+
+/// Write the last `n` entries to `filename`, appending them to the existing file if `append`.
+unsafe fn write(filename: *const c_char, n: usize, append: bool) -> c_int {
+    let mut content = Vec::new();
+    {
+        let history = history();
+        for entry in &history.entries[history.entries.len().saturating_sub(n)..] {
+            content.extend_from_slice(entry.line());
+            content.push(b'\n');
+        }
+    }
+    let mut options = OpenOptions::new();
+    if append {
+        // as readline, the file must exist
+        options.append(true);
+    } else {
+        options.write(true).create(true).truncate(true).mode(0o600);
+    }
+    errno(options.open(history_filename(filename)).and_then(|mut file| file.write_all(&content)))
+}
+
+pub unsafe fn write_history(filename: *const c_char) -> c_int {
+    write(filename, usize::MAX, false)
+}
+
+pub unsafe fn append_history(nelements: c_int, filename: *const c_char) -> c_int {
+    write(filename, nelements.max(0) as usize, true)
+}
+
+pub unsafe fn history_truncate_file(filename: *const c_char, nlines: c_int) -> c_int {
+    let path = history_filename(filename);
+    errno(fs::read(&path).and_then(|content| {
+        let lines = lines(&content);
+        let mut start = lines.len().saturating_sub(nlines.max(0) as usize);
+        // keep the timestamp of the first entry kept
+        if start > 0 && is_timestamp(lines[start - 1]) {
+            start -= 1;
+        }
+        let mut truncated = Vec::new();
+        for line in &lines[start..] {
+            truncated.extend_from_slice(line);
+            truncated.push(b'\n');
+        }
+        fs::write(&path, truncated)
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn lines() {
+        assert!(super::lines(b"").is_empty());
+        assert_eq!(super::lines(b"a\n\nb\n"), vec![&b"a"[..], b"", b"b"]);
+        assert_eq!(super::lines(b"a\nb"), vec![&b"a"[..], b"b"]);
+    }
+
+    #[test]
+    fn is_timestamp() {
+        assert!(super::is_timestamp(b"#1700000000"));
+        assert!(!super::is_timestamp(b"#"));
+        assert!(!super::is_timestamp(b"# comment"));
+        assert!(!super::is_timestamp(b"ls"));
+    }
+}
@@ -0,0 +1,18 @@
+//! The readline API implemented in Rust, for the `pure-rust` feature: no C library is linked.
+//!
+//! The line is edited over a terminal in raw mode with emacs-like key bindings,
+//! and the history file is read and written in readline's format.
+//! The statics and functions have the names of readline's so that the rest of the crate is unchanged.
+
+#![allow(non_upper_case_globals)]
+
+mod bindings;
+mod complete;
+mod editor;
+mod history;
+mod terminal;
+
+pub use self::bindings::*;
+pub use self::complete::*;
+pub use self::editor::*;
+pub use self::history::*;
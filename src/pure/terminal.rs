@@ -0,0 +1,91 @@
+//! Raw mode, input and size of the terminal.
+
+use std::io;
+use std::mem;
+use std::time::Duration;
+use libc::{c_int, termios};
+
+/// The terminal of `fd` in raw mode, restored when dropped.
+pub struct RawMode {
+    fd: c_int,
+    original: termios,
+}
+
+impl RawMode {
+    /// Put the terminal of `fd` in raw mode: no echo, no line buffering, no signal on special characters.
+    ///
+    /// Return `None` if `fd` is not a terminal.
+    pub fn enable(fd: c_int) -> Option<RawMode> {
+        unsafe {
+            if libc::isatty(fd) == 0 {
+                return None;
+            }
+            let mut original: termios = mem::zeroed();
+            if libc::tcgetattr(fd, &mut original) != 0 {
+                return None;
+            }
+            let mut raw = original;
+            raw.c_iflag &= !(libc::BRKINT | libc::ICRNL | libc::INPCK | libc::ISTRIP | libc::IXON);
+            // output processing is kept: "\n" still moves to the start of the next line
+            raw.c_lflag &= !(libc::ECHO | libc::ICANON | libc::IEXTEN | libc::ISIG);
+            raw.c_cc[libc::VMIN] = 1;
+            raw.c_cc[libc::VTIME] = 0;
+            if libc::tcsetattr(fd, libc::TCSADRAIN, &raw) != 0 {
+                return None;
+            }
+            Some(RawMode { fd, original })
+        }
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        unsafe { libc::tcsetattr(self.fd, libc::TCSADRAIN, &self.original) };
+    }
+}
+
+/// What reading a byte gave.
+pub enum Input {
+    Byte(u8),
+    Eof,
+    /// Nothing was read before the timeout.
+    Timeout,
+    /// A signal interrupted the read.
+    Interrupted,
+}
+
+/// Read a byte from `fd`, waiting at most `timeout` if any.
+pub fn read_byte(fd: c_int, timeout: Option<Duration>) -> Input {
+    unsafe {
+        if let Some(timeout) = timeout {
+            let mut pfd = libc::pollfd { fd, events: libc::POLLIN, revents: 0 };
+            match libc::poll(&mut pfd, 1, timeout.as_millis().min(c_int::MAX as u128) as c_int) {
+                0 => return Input::Timeout,
+                n if n < 0 => return if interrupted() { Input::Interrupted } else { Input::Eof },
+                _ => {}
+            }
+        }
+        let mut byte = 0u8;
+        match libc::read(fd, &mut byte as *mut u8 as *mut libc::c_void, 1) {
+            1 => Input::Byte(byte),
+            n if n < 0 && interrupted() => Input::Interrupted,
+            _ => Input::Eof,
+        }
+    }
+}
+
+fn interrupted() -> bool {
+    io::Error::last_os_error().kind() == io::ErrorKind::Interrupted
+}
+
+/// Return the (rows, columns) of the terminal of `fd`.
+pub fn size(fd: c_int) -> Option<(usize, usize)> {
+    unsafe {
+        let mut size: libc::winsize = mem::zeroed();
+        if libc::ioctl(fd, libc::TIOCGWINSZ, &mut size) == 0 && size.ws_col > 0 {
+            Some((size.ws_row as usize, size.ws_col as usize))
+        } else {
+            None
+        }
+    }
+}
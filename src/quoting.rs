@@ -0,0 +1,268 @@
+//! Quoting of completed filenames containing spaces or special characters.
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use libc::{c_char, c_int};
+
+use super::ffi;
+
+/// Characters quoted by the default quoting functions.
+pub const SPECIAL_CHARACTERS: &str = " \t\n\\\"'@<>=;|&()#$`?*[]!:{}";
+
+/// A quoting function receiving a completion match, whether it is the only match,
+/// and the quote character opening the word being completed, if any.
+///
+/// It returns the quoted match, starting with the opening quote but without the closing one
+/// (readline appends it when there is a single match).
+pub type QuotingFunction = Box<dyn FnMut(&str, bool, Option<char>) -> String>;
+/// A dequoting function receiving a word and the quote character opening it, if any.
+pub type DequotingFunction = Box<dyn FnMut(&str, Option<char>) -> String>;
+/// A function saying if the character at the byte offset `index` of the line is quoted.
+pub type CharIsQuotedFunction = Box<dyn FnMut(&str, usize) -> bool>;
+
+/// Predefined ways of quoting completed filenames.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum QuoteStyle {
+    /// Special characters are preceded by a backslash: `my\ file`.
+    Backslash,
+    /// Matches containing special characters are enclosed in single quotes: `'my file'`.
+    SingleQuote,
+}
+
+/// Escape the special characters of `text` with backslashes,
+/// or quote it with `quote_char` if the word being completed is already quoted.
+pub fn backslash_quote(text: &str, _single_match: bool, quote_char: Option<char>) -> String {
+    match quote_char {
+        Some(quote_char) => quote(text, quote_char),
+        None => {
+            let mut quoted = String::with_capacity(text.len());
+            for c in text.chars() {
+                if SPECIAL_CHARACTERS.contains(c) {
+                    quoted.push('\\');
+                }
+                quoted.push(c);
+            }
+            quoted
+        }
+    }
+}
+
+/// Quote `text` with single quotes, or with `quote_char` if the word being completed is already quoted.
+pub fn single_quote(text: &str, _single_match: bool, quote_char: Option<char>) -> String {
+    quote(text, quote_char.unwrap_or('\''))
+}
+
+fn quote(text: &str, quote_char: char) -> String {
+    let mut quoted = String::with_capacity(text.len() + 1);
+    quoted.push(quote_char);
+    for c in text.chars() {
+        match (quote_char, c) {
+            // close, escape and reopen
+            ('\'', '\'') => quoted.push_str("'\\''"),
+            ('"', '"') | ('"', '\\') | ('"', '$') | ('"', '`') => {
+                quoted.push('\\');
+                quoted.push(c);
+            }
+            _ => quoted.push(c),
+        }
+    }
+    quoted
+}
+
+/// Remove the quotes and backslashes from `text`, which starts inside `quote_char` quotes if any.
+pub fn dequote(text: &str, quote_char: Option<char>) -> String {
+    let mut dequoted = String::with_capacity(text.len());
+    let mut quote = quote_char;
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some('\''), '\'') => quote = None,
+            (Some('\''), _) => dequoted.push(c),
+            (Some(q), c) if c == q => quote = None,
+            (_, '\\') => dequoted.extend(chars.next()),
+            (None, '\'') | (None, '"') => quote = Some(c),
+            _ => dequoted.push(c),
+        }
+    }
+    dequoted
+}
+
+/// Say if the character at the byte offset `index` of `line` is escaped by a backslash or enclosed in quotes.
+pub fn char_is_quoted(line: &str, index: usize) -> bool {
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        if i == index {
+            return escaped || quote.is_some();
+        }
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match (quote, c) {
+            (Some('\''), '\'') => quote = None,
+            (Some('\''), _) => {}
+            (Some(q), c) if c == q => quote = None,
+            (_, '\\') => escaped = true,
+            (None, '\'') | (None, '"') => quote = Some(c),
+            _ => {}
+        }
+    }
+    false
+}
+
+thread_local! {
+    static QUOTING_FUNCTION: RefCell<Option<QuotingFunction>> = const { RefCell::new(None) };
+    static DEQUOTING_FUNCTION: RefCell<Option<DequotingFunction>> = const { RefCell::new(None) };
+    static CHAR_IS_QUOTED: RefCell<Option<CharIsQuotedFunction>> = const { RefCell::new(None) };
+}
+
+// rl_filename_quoting_function match types
+const SINGLE_MATCH: c_int = 1;
+
+fn quote_char(c: c_int) -> Option<char> {
+    if c == 0 { None } else { Some(c as u8 as char) }
+}
+
+/// Return `f(text)` as a string freed by readline, or a copy of `text` when it is not UTF-8
+/// (a filename of another encoding, which the replacement characters would change).
+fn rewrite<F>(text: *const c_char, f: F) -> *mut c_char where F: FnOnce(&str) -> String {
+    let text = unsafe { CStr::from_ptr(text) };
+    let new_text = text.to_str().ok().and_then(|text| CString::new(f(text)).ok());
+    unsafe { ffi::strdup(new_text.as_deref().unwrap_or(text).as_ptr()) as *mut c_char }
+}
+
+extern "C" fn filename_quoting_function(text: *mut c_char, match_type: c_int, quote_pointer: *mut c_char) -> *mut c_char {
+    let qc = if quote_pointer.is_null() { None } else { quote_char(unsafe { *quote_pointer } as c_int) };
+    rewrite(text, |text| QUOTING_FUNCTION.with(|f| match *f.borrow_mut() {
+        Some(ref mut f) => f(text, match_type == SINGLE_MATCH, qc),
+        None => text.to_string(),
+    }))
+}
+
+extern "C" fn filename_dequoting_function(text: *mut c_char, quote_c: c_int) -> *mut c_char {
+    rewrite(text, |text| DEQUOTING_FUNCTION.with(|f| match *f.borrow_mut() {
+        Some(ref mut f) => f(text, quote_char(quote_c)),
+        None => text.to_string(),
+    }))
+}
+
+extern "C" fn char_is_quoted_p(text: *mut c_char, index: c_int) -> c_int {
+    let line = unsafe { CStr::from_ptr(text).to_bytes() };
+    let line = String::from_utf8_lossy(line);
+    CHAR_IS_QUOTED.with(|f| match *f.borrow_mut() {
+        Some(ref mut f) => f(&line, index as usize) as c_int,
+        None => 0,
+    })
+}
+
+/// Set the function quoting the completed filenames which contain characters of `rl_filename_quote_characters`.
+///
+/// Filenames which are not valid UTF-8 are inserted unquoted.
+/// (See [rl_filename_quoting_function](http://cnswww.cns.cwru.edu/php/chet/readline/readline.html))
+pub fn set_rl_filename_quoting_function(f: Option<QuotingFunction>) {
+    let installed = f.is_some();
+    QUOTING_FUNCTION.with(|q| *q.borrow_mut() = f);
+    unsafe { ffi::rl_filename_quoting_function = if installed { Some(filename_quoting_function) } else { None } }
+}
+
+/// Set the function removing the quotes from the word being completed before matching it against filenames.
+///
+/// Words which are not valid UTF-8 are matched as they are.
+/// (See [rl_filename_dequoting_function](http://cnswww.cns.cwru.edu/php/chet/readline/readline.html))
+pub fn set_rl_filename_dequoting_function(f: Option<DequotingFunction>) {
+    let installed = f.is_some();
+    DEQUOTING_FUNCTION.with(|d| *d.borrow_mut() = f);
+    unsafe { ffi::rl_filename_dequoting_function = if installed { Some(filename_dequoting_function) } else { None } }
+}
+
+/// Set the function deciding whether a character of the line is quoted,
+/// so that it is not considered as a word break character.
+///
+/// (See [rl_char_is_quoted_p](http://cnswww.cns.cwru.edu/php/chet/readline/readline.html))
+pub fn set_rl_char_is_quoted_p(f: Option<CharIsQuotedFunction>) {
+    let installed = f.is_some();
+    CHAR_IS_QUOTED.with(|q| *q.borrow_mut() = f);
+    unsafe { ffi::rl_char_is_quoted_p = if installed { Some(char_is_quoted_p) } else { None } }
+}
+
+/// Quote completed filenames with `style`, shell-like.
+///
+/// This sets the quote characters (single and double quotes), the characters which need quoting,
+/// and the quoting, dequoting and `rl_char_is_quoted_p` functions.
+pub fn set_quote_style(style: QuoteStyle) {
+    super::set_rl_completer_quote_characters("'\"");
+    super::set_rl_filename_quote_characters(SPECIAL_CHARACTERS);
+    set_rl_filename_quoting_function(Some(match style {
+        QuoteStyle::Backslash => Box::new(backslash_quote),
+        QuoteStyle::SingleQuote => Box::new(single_quote),
+    }));
+    set_rl_filename_dequoting_function(Some(Box::new(dequote)));
+    set_rl_char_is_quoted_p(Some(Box::new(char_is_quoted)));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CStr;
+    use std::ptr;
+    use libc::{c_char, c_void};
+
+    #[test]
+    fn backslash_quote() {
+        assert_eq!(super::backslash_quote("my file (1).txt", true, None), "my\\ file\\ \\(1\\).txt");
+        assert_eq!(super::backslash_quote("my file", true, Some('"')), "\"my file");
+    }
+
+    #[test]
+    fn single_quote() {
+        assert_eq!(super::single_quote("my file", true, None), "'my file");
+        assert_eq!(super::single_quote("it's", true, None), "'it'\\''s");
+        assert_eq!(super::single_quote("say \"hi\"", false, Some('"')), "\"say \\\"hi\\\"");
+    }
+
+    #[test]
+    fn dequote() {
+        assert_eq!(super::dequote("my\\ file", None), "my file");
+        assert_eq!(super::dequote("my file", Some('\'')), "my file");
+        assert_eq!(super::dequote("'it'\\''s'", None), "it's");
+        assert_eq!(super::dequote("a\\\"b\"c", Some('"')), "a\"bc");
+    }
+
+    #[test]
+    fn char_is_quoted() {
+        let line = "cp my\\ file 'a b' c";
+        assert!(super::char_is_quoted(line, 6));
+        assert!(!super::char_is_quoted(line, 2));
+        assert!(super::char_is_quoted(line, 14));
+        assert!(!super::char_is_quoted(line, 17));
+    }
+
+    #[test]
+    fn filename_quoting_function() {
+        let quote = |text: &CStr| unsafe {
+            let quoted = super::filename_quoting_function(text.as_ptr() as *mut c_char, super::SINGLE_MATCH, ptr::null_mut());
+            let copy = CStr::from_ptr(quoted).to_owned();
+            libc::free(quoted as *mut c_void);
+            copy
+        };
+        super::set_rl_filename_quoting_function(Some(Box::new(super::backslash_quote)));
+        assert_eq!(quote(c"my file").as_c_str(), c"my\\ file");
+        // not UTF-8: unchanged
+        assert_eq!(quote(c"caf\xe9 1").as_c_str(), c"caf\xe9 1");
+        super::set_rl_filename_quoting_function(None);
+    }
+
+    #[test]
+    fn filename_dequoting_function() {
+        let dequote = |text: &CStr| unsafe {
+            let dequoted = super::filename_dequoting_function(text.as_ptr() as *mut c_char, 0);
+            let copy = CStr::from_ptr(dequoted).to_owned();
+            libc::free(dequoted as *mut c_void);
+            copy
+        };
+        super::set_rl_filename_dequoting_function(Some(Box::new(super::dequote)));
+        assert_eq!(dequote(c"my\\ file").as_c_str(), c"my file");
+        assert_eq!(dequote(c"caf\xe9\\ 1").as_c_str(), c"caf\xe9\\ 1");
+        super::set_rl_filename_dequoting_function(None);
+    }
+}
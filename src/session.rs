@@ -0,0 +1,152 @@
+//! Reading lines from other file descriptors than stdin and stdout.
+
+use std::cell::Cell;
+use std::ffi::CStr;
+use std::io::{Error, Result};
+use std::os::unix::io::RawFd;
+use std::sync::OnceLock;
+use libc::FILE;
+
+use super::ffi;
+
+thread_local! {
+    // the streams of the innermost session
+    static STREAMS: Cell<Option<(*mut FILE, *mut FILE)>> = const { Cell::new(None) };
+}
+
+/// The input and output streams of the current session, if any.
+pub fn streams() -> Option<(*mut FILE, *mut FILE)> {
+    STREAMS.with(|s| s.get())
+}
+
+/// Readline running on a pty, a serial port, a socket or any other pair of file descriptors
+/// (the `rl_instream` and `rl_outstream` variables).
+///
+/// ```no_run
+/// # use std::os::unix::io::AsRawFd;
+/// let pty = std::fs::OpenOptions::new().read(true).write(true).open("/dev/pts/3").unwrap();
+/// let session = readline::ReadlineSession::with_fds(pty.as_raw_fd(), pty.as_raw_fd()).unwrap();
+/// while let Some(line) = session.readline("admin> ") {
+///     // ...
+/// }
+/// ```
+///
+/// The streams are used by all the functions of the crate until the session is dropped,
+/// which restores the previous ones. libedit reads them once: with the `libedit` feature,
+/// the session must be created before any other function of the crate is called.
+pub struct ReadlineSession {
+    input: *mut FILE,
+    output: *mut FILE,
+    previous: (*mut FILE, *mut FILE),
+    previous_streams: Option<(*mut FILE, *mut FILE)>,
+}
+
+/// Open a stream on a duplicate of `fd`, so that closing it leaves `fd` open.
+fn open(fd: RawFd, mode: &CStr) -> Result<*mut FILE> {
+    unsafe {
+        let dup = libc::dup(fd);
+        if dup < 0 {
+            return Err(Error::last_os_error());
+        }
+        let stream = libc::fdopen(dup, mode.as_ptr());
+        if stream.is_null() {
+            let err = Error::last_os_error();
+            libc::close(dup);
+            return Err(err);
+        }
+        Ok(stream)
+    }
+}
+
+/// Streams on stdin and stdout, opened once.
+struct StandardStreams(*mut FILE, *mut FILE);
+
+// only ever given to readline
+unsafe impl Send for StandardStreams {}
+unsafe impl Sync for StandardStreams {}
+
+fn standard_streams() -> &'static StandardStreams {
+    static STANDARD: OnceLock<StandardStreams> = OnceLock::new();
+    STANDARD.get_or_init(|| unsafe { StandardStreams(libc::fdopen(0, c"r".as_ptr()), libc::fdopen(1, c"w".as_ptr())) })
+}
+
+/// Return `stream`, or `standard` if it is `NULL`: readline does not expect
+/// its streams to be reset to `NULL` once it is initialized.
+fn or_standard(stream: *mut FILE, standard: *mut FILE) -> *mut FILE {
+    if stream.is_null() {
+        standard
+    } else {
+        stream
+    }
+}
+
+impl ReadlineSession {
+    /// Make readline read from `input` and write to `output` until the session is dropped.
+    ///
+    /// The file descriptors are duplicated: they can be closed by the caller once the session is dropped.
+    pub fn with_fds(input: RawFd, output: RawFd) -> Result<ReadlineSession> {
+        let input = open(input, c"r")?;
+        let output = match open(output, c"w") {
+            Ok(output) => output,
+            Err(err) => {
+                unsafe { libc::fclose(input) };
+                return Err(err);
+            }
+        };
+        let previous = unsafe {
+            let standard = standard_streams();
+            let previous = (or_standard(ffi::rl_instream, standard.0), or_standard(ffi::rl_outstream, standard.1));
+            ffi::rl_instream = input;
+            ffi::rl_outstream = output;
+            previous
+        };
+        let previous_streams = STREAMS.with(|s| s.replace(Some((input, output))));
+        Ok(ReadlineSession { input, output, previous, previous_streams })
+    }
+
+    /// Read a line like `readline()`, from the file descriptors of the session.
+    pub fn readline(&self, prompt: &str) -> Option<String> {
+        super::readline(prompt)
+    }
+}
+
+impl Drop for ReadlineSession {
+    fn drop(&mut self) {
+        STREAMS.with(|s| s.set(self.previous_streams));
+        unsafe {
+            ffi::rl_instream = self.previous.0;
+            ffi::rl_outstream = self.previous.1;
+            libc::fclose(self.input);
+            libc::fclose(self.output);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libc::c_int;
+    use super::ReadlineSession;
+
+    #[test]
+    fn with_fds() {
+        let (mut input, mut output) = ([0 as c_int; 2], [0 as c_int; 2]);
+        unsafe {
+            assert_eq!(libc::pipe(input.as_mut_ptr()), 0);
+            assert_eq!(libc::pipe(output.as_mut_ptr()), 0);
+            libc::write(input[1], b"line\n".as_ptr() as *const libc::c_void, 5);
+        }
+        {
+            let session = ReadlineSession::with_fds(input[0], output[1]).unwrap();
+            assert_eq!(super::streams().map(|s| s.0), Some(session.input));
+            assert_eq!(session.readline("> "), Some("line".to_string()));
+        }
+        assert!(super::streams().is_none());
+        let mut buf = [0u8; 16];
+        let n = unsafe { libc::read(output[0], buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+        assert_eq!(&buf[..n as usize], b"> ");
+        for fd in input.iter().chain(output.iter()) {
+            // still open
+            assert_eq!(unsafe { libc::close(*fd) }, 0);
+        }
+    }
+}
@@ -0,0 +1,296 @@
+//! Signal handling, Control-C in a REPL, and the size of the screen.
+
+use std::cell::RefCell;
+use std::error;
+use std::fmt;
+use std::mem;
+use std::sync::OnceLock;
+use libc::{c_char, c_int, c_void};
+
+use super::{display, ffi, hooks, plain};
+
+/// Say if readline installs its own handlers for SIGINT, SIGTERM, SIGHUP, SIGQUIT, SIGALRM and SIGTSTP,
+/// restoring the terminal before the application's handlers run, the default is true.
+///
+/// (See [rl_catch_signals](http://cnswww.cns.cwru.edu/php/chet/readline/readline.html))
+pub fn rl_catch_signals() -> bool {
+    unsafe { ffi::rl_catch_signals != 0 }
+}
+
+/// Set whether readline installs its own signal handlers. It is read each time readline starts reading a line.
+///
+/// (See [rl_catch_signals](http://cnswww.cns.cwru.edu/php/chet/readline/readline.html))
+pub fn set_rl_catch_signals(b: bool) {
+    unsafe { ffi::rl_catch_signals = b as c_int; }
+}
+
+/// Say if readline handles SIGWINCH, redisplaying the line at the new screen size, the default is true.
+///
+/// (See [rl_catch_sigwinch](http://cnswww.cns.cwru.edu/php/chet/readline/readline.html))
+pub fn rl_catch_sigwinch() -> bool {
+    unsafe { ffi::rl_catch_sigwinch != 0 }
+}
+
+/// Set whether readline handles SIGWINCH. When it does not, the application should call `rl_resize_terminal()`.
+///
+/// (See [rl_catch_sigwinch](http://cnswww.cns.cwru.edu/php/chet/readline/readline.html))
+pub fn set_rl_catch_sigwinch(b: bool) {
+    unsafe { ffi::rl_catch_sigwinch = b as c_int; }
+}
+
+/// Restore the terminal and remove readline's signal handlers, from an application's signal handler.
+///
+/// (See [rl_cleanup_after_signal](http://cnswww.cns.cwru.edu/php/chet/readline/readline.html))
+pub fn rl_cleanup_after_signal() {
+    unsafe { ffi::rl_cleanup_after_signal() }
+}
+
+/// Free the partial state of the line being read (undo list, pending key sequence, numeric argument),
+/// before `rl_cleanup_after_signal()` when the line is abandoned.
+///
+/// (See [rl_free_line_state](http://cnswww.cns.cwru.edu/php/chet/readline/readline.html))
+pub fn rl_free_line_state() {
+    unsafe { ffi::rl_free_line_state() }
+}
+
+/// Prepare the terminal and install readline's signal handlers again, after `rl_cleanup_after_signal()`.
+///
+/// (See [rl_reset_after_signal](http://cnswww.cns.cwru.edu/php/chet/readline/readline.html))
+pub fn rl_reset_after_signal() {
+    unsafe { ffi::rl_reset_after_signal() }
+}
+
+/// Read the size of the terminal again and redisplay the line, when SIGWINCH is not caught by readline.
+///
+/// (See [rl_resize_terminal](http://cnswww.cns.cwru.edu/php/chet/readline/readline.html))
+pub fn rl_resize_terminal() {
+    unsafe {
+        // readline reads the size of the terminal of `rl_instream`
+        if ffi::rl_instream.is_null() {
+            ffi::rl_initialize();
+        }
+        ffi::rl_resize_terminal()
+    }
+}
+
+/// Make readline display lines on a screen of `rows` by `cols`, whatever the size of the terminal.
+///
+/// (See [rl_set_screen_size](http://cnswww.cns.cwru.edu/php/chet/readline/readline.html))
+pub fn rl_set_screen_size(rows: i32, cols: i32) {
+    unsafe { ffi::rl_set_screen_size(rows, cols) }
+}
+
+/// Return the (rows, columns) of the screen as readline knows it.
+///
+/// (See [rl_get_screen_size](http://cnswww.cns.cwru.edu/php/chet/readline/readline.html))
+pub fn rl_get_screen_size() -> (i32, i32) {
+    let (mut rows, mut cols) = (0, 0);
+    unsafe { ffi::rl_get_screen_size(&mut rows, &mut cols) };
+    (rows, cols)
+}
+
+/// Control-C was typed (or SIGINT received) while `readline_interruptible` was reading a line.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Interrupted;
+
+impl fmt::Display for Interrupted {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("interrupted")
+    }
+}
+
+impl error::Error for Interrupted {}
+
+/// A pipe written by the SIGINT handler, so that the wait for input is interrupted
+/// whichever thread the signal is delivered to.
+fn sigint_pipe() -> [c_int; 2] {
+    static PIPE: OnceLock<[c_int; 2]> = OnceLock::new();
+    *PIPE.get_or_init(|| {
+        let mut fds = [-1 as c_int; 2];
+        unsafe {
+            if libc::pipe(fds.as_mut_ptr()) == 0 {
+                for &fd in &fds {
+                    libc::fcntl(fd, libc::F_SETFL, libc::fcntl(fd, libc::F_GETFL) | libc::O_NONBLOCK);
+                    libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC);
+                }
+            }
+        }
+        fds
+    })
+}
+
+extern "C" fn on_sigint(_signal: c_int) {
+    unsafe { libc::write(sigint_pipe()[1], b"!".as_ptr() as *const c_void, 1) };
+}
+
+/// Say if SIGINT was received since the last call.
+fn sigint_received() -> bool {
+    let mut buf = [0u8; 16];
+    let mut received = false;
+    while unsafe { libc::read(sigint_pipe()[0], buf.as_mut_ptr() as *mut c_void, buf.len()) } > 0 {
+        received = true;
+    }
+    received
+}
+
+/// SIGINT handled by `on_sigint`, and readline's own signal handling disabled, until dropped.
+struct SigintGuard {
+    previous: libc::sigaction,
+    catch_signals: c_int,
+}
+
+impl SigintGuard {
+    fn install() -> SigintGuard {
+        sigint_received();
+        unsafe {
+            let mut action: libc::sigaction = mem::zeroed();
+            action.sa_sigaction = on_sigint as *const () as usize;
+            libc::sigemptyset(&mut action.sa_mask);
+            let mut previous: libc::sigaction = mem::zeroed();
+            libc::sigaction(libc::SIGINT, &action, &mut previous);
+            let catch_signals = ffi::rl_catch_signals;
+            ffi::rl_catch_signals = 0;
+            SigintGuard { previous, catch_signals }
+        }
+    }
+}
+
+impl Drop for SigintGuard {
+    fn drop(&mut self) {
+        unsafe {
+            ffi::rl_catch_signals = self.catch_signals;
+            libc::sigaction(libc::SIGINT, &self.previous, std::ptr::null_mut());
+        }
+    }
+}
+
+thread_local! {
+    // the line given to `line_handler`, `Some(None)` at end of file
+    static LINE: RefCell<Option<Option<String>>> = const { RefCell::new(None) };
+}
+
+extern "C" fn line_handler(line: *mut c_char) {
+    // removed before readline displays the prompt again
+    super::rl_callback_handler_remove();
+    let line = if line.is_null() {
+        None
+    } else {
+        // replacing invalid UTF-8, as `readline()` does: a panic would abort in this callback
+        let copy = super::c_str_to_string(line);
+        unsafe { libc::free(line as *mut c_void) };
+        copy
+    };
+    LINE.with(|l| *l.borrow_mut() = Some(line));
+}
+
+/// Wait until there is input or SIGINT is received, calling the event hook meanwhile if any.
+/// Return `true` if there is input.
+fn wait_for_input() -> bool {
+    let input = unsafe { if ffi::rl_instream.is_null() { 0 } else { libc::fileno(ffi::rl_instream) } };
+    let mut fds = [
+        libc::pollfd { fd: input, events: libc::POLLIN, revents: 0 },
+        libc::pollfd { fd: sigint_pipe()[0], events: libc::POLLIN, revents: 0 },
+    ];
+    let event_hook = unsafe { ffi::rl_event_hook };
+    let timeout = match event_hook {
+        // the current timeout is read without being changed
+        Some(_) => (hooks::rl_set_keyboard_input_timeout(-1) / 1000).max(1),
+        None => -1,
+    };
+    match unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, timeout) } {
+        0 => {
+            if let Some(hook) = event_hook {
+                hook();
+            }
+            false
+        }
+        n if n > 0 => fds[0].revents != 0,
+        // interrupted by a signal
+        _ => false,
+    }
+}
+
+/// Leave the interrupted line: display it whole followed by "^C", and restore the terminal.
+fn abandon_line() {
+    unsafe {
+        // libedit's `rl_redisplay` queues a reprint key instead
+        #[cfg(not(feature = "libedit"))]
+        {
+            ffi::rl_point = ffi::rl_end;
+            ffi::rl_redisplay();
+        }
+        // before the terminal is restored: GNU readline then moves to the start of the line
+        display::write_out("^C");
+        ffi::rl_free_line_state();
+        ffi::rl_cleanup_after_signal();
+    }
+    display::write_out("\n");
+    super::rl_callback_handler_remove();
+}
+
+/// Read a line like `readline()`, returning `Err(Interrupted)` when Control-C is typed:
+/// the line is abandoned, "^C" is printed and the terminal restored, instead of the process being killed.
+///
+/// Calling it again prompts on a new line, as shells do:
+///
+/// ```no_run
+/// loop {
+///     match readline::readline_interruptible("> ") {
+///         Ok(Some(line)) => println!("{}", line),
+///         Ok(None) => break,
+///         Err(readline::Interrupted) => continue,
+///     }
+/// }
+/// ```
+///
+/// SIGINT is handled, and readline's own signal handling (see `rl_catch_signals`) disabled,
+/// only while the line is read. When lines are not read interactively (see `Mode`), SIGINT is not handled.
+pub fn readline_interruptible(prompt: &str) -> Result<Option<String>, Interrupted> {
+    if !plain::is_interactive() {
+        return Ok(plain::readline(prompt));
+    }
+    let _guard = SigintGuard::install();
+    LINE.with(|l| l.borrow_mut().take());
+    super::rl_callback_handler_install(prompt, Some(line_handler));
+    loop {
+        if let Some(line) = LINE.with(|l| l.borrow_mut().take()) {
+            return Ok(line);
+        }
+        if sigint_received() {
+            abandon_line();
+            return Err(Interrupted);
+        }
+        // keys from `rl_getc_function` are read when asked for
+        if hooks::has_getc_function() || wait_for_input() {
+            super::rl_callback_read_char();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn catch_signals() {
+        assert!(super::rl_catch_signals(), "signals are expected to be caught by default");
+        super::set_rl_catch_signals(false);
+        assert!(!super::rl_catch_signals());
+        super::set_rl_catch_signals(true);
+    }
+
+    #[test]
+    fn sigint_received() {
+        assert!(!super::sigint_received());
+        super::on_sigint(libc::SIGINT);
+        super::on_sigint(libc::SIGINT);
+        assert!(super::sigint_received());
+        assert!(!super::sigint_received());
+    }
+
+    #[test]
+    fn line_handler() {
+        let line = unsafe { super::super::ffi::strdup(c"caf\xe9".as_ptr()) };
+        super::line_handler(line as *mut libc::c_char);
+        let line = super::LINE.with(|l| l.borrow_mut().take());
+        assert_eq!(line, Some(Some("caf\u{fffd}".to_string())));
+    }
+}
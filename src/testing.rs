@@ -0,0 +1,296 @@
+//! Running readline on a pseudo-terminal, for tests of line editing, completion and key bindings.
+//!
+//! ```no_run
+//! # use readline::CompletionContext;
+//! use readline::testing::Pty;
+//!
+//! let mut pty = Pty::new().unwrap();
+//! readline::set_completer(Some(Box::new(|_: &CompletionContext| vec!["alpha".to_string(), "beta".to_string()])));
+//! let outcome = pty.readline("> ", "al\t\r").unwrap();
+//! assert_eq!(outcome.line.as_deref(), Some("alpha "));
+//! assert_eq!(outcome.screen, ["> alpha"]);
+//! ```
+//!
+//! Readline is not thread-safe: tests using a `Pty` must not run concurrently with other tests calling readline.
+//! With libedit, the `Pty` must be created before any other function of the crate is called
+//! (see `ReadlineSession`).
+
+use std::io::{Error, Result};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use std::ptr;
+use libc::{c_int, c_void};
+use unicode_width::UnicodeWidthChar;
+
+use super::display;
+use super::session::ReadlineSession;
+
+/// What `Pty::readline` (or `Pty::read_with`) read and displayed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Outcome<T = Option<String>> {
+    /// The line returned by readline, `None` at end of file.
+    pub line: T,
+    /// The bytes written to the terminal, escape sequences included.
+    pub output: Vec<u8>,
+    /// The rows of the screen once `output` is displayed, without trailing spaces or empty rows.
+    pub screen: Vec<String>,
+}
+
+/// A pseudo-terminal pair: readline runs on the slave side and the keys are typed on the master side.
+pub struct Pty {
+    session: Option<ReadlineSession>,
+    master: c_int,
+    slave: c_int,
+    cols: usize,
+    output: Arc<Mutex<Vec<u8>>>,
+    stop: Arc<AtomicBool>,
+    reader: Option<JoinHandle<()>>,
+}
+
+fn check(result: c_int) -> Result<c_int> {
+    if result < 0 { Err(Error::last_os_error()) } else { Ok(result) }
+}
+
+impl Pty {
+    /// Open a 24x80 pseudo-terminal and make readline use it until the `Pty` is dropped.
+    pub fn new() -> Result<Pty> {
+        Pty::with_size(24, 80)
+    }
+
+    /// Open a pseudo-terminal of `rows` by `cols` and make readline use it until the `Pty` is dropped.
+    pub fn with_size(rows: u16, cols: u16) -> Result<Pty> {
+        let (mut master, mut slave) = (0, 0);
+        let size = libc::winsize { ws_row: rows, ws_col: cols, ws_xpixel: 0, ws_ypixel: 0 };
+        unsafe { check(libc::openpty(&mut master, &mut slave, ptr::null_mut(), ptr::null(), &size))? };
+        let session = match ReadlineSession::with_fds(slave, slave) {
+            Ok(session) => session,
+            Err(err) => {
+                unsafe {
+                    libc::close(master);
+                    libc::close(slave);
+                }
+                return Err(err);
+            }
+        };
+        let output = Arc::new(Mutex::new(Vec::new()));
+        let stop = Arc::new(AtomicBool::new(false));
+        let reader = {
+            let (output, stop) = (output.clone(), stop.clone());
+            thread::spawn(move || read_output(master, &output, &stop))
+        };
+        Ok(Pty { session: Some(session), master, slave, cols: cols as usize, output, stop, reader: Some(reader) })
+    }
+
+    /// Type `keys` (for example "ab\t\r") and read a line with `prompt`.
+    ///
+    /// The keys must end the line (with RETURN, or Control-D on an empty line), otherwise readline waits for more.
+    pub fn readline(&mut self, prompt: &str, keys: &str) -> Result<Outcome> {
+        self.read_with(keys, || super::readline(prompt))
+    }
+
+    /// Type `keys` and call `read`, which reads from the pty: with `readline_multiline` or `readline_interruptible`
+    /// for example. The `line` of the outcome is what `read` returned.
+    pub fn read_with<T, F>(&mut self, keys: &str, read: F) -> Result<Outcome<T>> where F: FnOnce() -> T {
+        self.take_output();
+        let master = self.master;
+        let keys = keys.as_bytes().to_vec();
+        // typed once readline has prepared the terminal, so that the line discipline does not echo them
+        let typist = thread::spawn(move || {
+            wait_for_raw_mode(master);
+            type_keys(master, &keys)
+        });
+        let line = read();
+        typist.join().unwrap_or_else(|_| Err(Error::other("typing the keys failed")))?;
+        Ok(self.outcome(line))
+    }
+
+    /// Type `keys` without reading them, for example after `rl_callback_handler_install`
+    /// and before calling `rl_callback_read_char`.
+    pub fn type_keys(&mut self, keys: &str) -> Result<()> {
+        type_keys(self.master, keys.as_bytes())
+    }
+
+    /// Return the bytes written to the terminal since the last call, once readline has flushed them.
+    pub fn take_output(&mut self) -> Vec<u8> {
+        // The bytes written on the slave side reach the master side asynchronously:
+        // wait until the reader thread has read them all and nothing came for a while.
+        let mut quiet = 0;
+        while quiet < 10 {
+            let mut pending: c_int = 0;
+            if unsafe { libc::ioctl(self.master, libc::FIONREAD, &mut pending) } < 0 {
+                break;
+            }
+            quiet = if pending == 0 { quiet + 1 } else { 0 };
+            thread::sleep(Duration::from_millis(1));
+        }
+        std::mem::take(&mut *self.output.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    fn outcome<T>(&mut self, line: T) -> Outcome<T> {
+        let output = self.take_output();
+        let screen = screen(&String::from_utf8_lossy(&output), self.cols);
+        Outcome { line, output, screen }
+    }
+}
+
+impl Drop for Pty {
+    fn drop(&mut self) {
+        self.session.take();
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(reader) = self.reader.take() {
+            let _ = reader.join();
+        }
+        unsafe {
+            libc::close(self.slave);
+            libc::close(self.master);
+        }
+    }
+}
+
+fn type_keys(master: c_int, mut keys: &[u8]) -> Result<()> {
+    while !keys.is_empty() {
+        let n = unsafe { check(libc::write(master, keys.as_ptr() as *const c_void, keys.len()) as c_int)? };
+        keys = &keys[n as usize..];
+    }
+    Ok(())
+}
+
+/// Wait, for a second at most, until the terminal is no longer in canonical mode.
+fn wait_for_raw_mode(master: c_int) {
+    for _ in 0..1000 {
+        let mut termios = unsafe { std::mem::zeroed::<libc::termios>() };
+        if unsafe { libc::tcgetattr(master, &mut termios) } != 0 || termios.c_lflag & libc::ICANON == 0 {
+            return;
+        }
+        thread::sleep(Duration::from_millis(1));
+    }
+}
+
+/// Append what is written to the terminal to `output` until `stop` is set.
+fn read_output(master: c_int, output: &Mutex<Vec<u8>>, stop: &AtomicBool) {
+    let mut buf = [0u8; 4096];
+    while !stop.load(Ordering::SeqCst) {
+        let mut pfd = libc::pollfd { fd: master, events: libc::POLLIN, revents: 0 };
+        if unsafe { libc::poll(&mut pfd, 1, 10) } <= 0 {
+            continue;
+        }
+        // locked while reading, so that `take_output` gets what was read
+        let mut output = output.lock().unwrap_or_else(|e| e.into_inner());
+        match unsafe { libc::read(master, buf.as_mut_ptr() as *mut c_void, buf.len()) } {
+            n if n > 0 => output.extend_from_slice(&buf[..n as usize]),
+            _ => return,
+        }
+    }
+}
+
+/// Display `output` on a screen `cols` wide and return its rows.
+///
+/// Only what readline uses is understood: the cursor movements, erasing and line wrapping.
+fn screen(output: &str, cols: usize) -> Vec<String> {
+    // the second column of a wide character is `None`
+    let mut rows: Vec<Vec<Option<char>>> = vec![Vec::new()];
+    let (mut row, mut col) = (0usize, 0usize);
+    let mut rest = output;
+    while let Some(c) = rest.chars().next() {
+        if c == '\x1b' {
+            let len = display::escape_len(rest);
+            let seq = &rest[..len];
+            rest = &rest[len..];
+            if let Some(csi) = seq.strip_prefix("\x1b[") {
+                let (params, command) = csi.split_at(csi.len() - command_len(csi));
+                let n = |default: usize| params.split(';').next().and_then(|p| p.parse().ok()).unwrap_or(default);
+                match command {
+                    "A" => row = row.saturating_sub(n(1)),
+                    "B" => row += n(1),
+                    "C" => col = (col + n(1)).min(cols - 1),
+                    "D" => col = col.saturating_sub(n(1)),
+                    "G" => col = n(1).clamp(1, cols) - 1,
+                    "H" | "f" => {
+                        let mut p = params.split(';').map(|p| p.parse::<usize>().unwrap_or(1).max(1));
+                        row = p.next().unwrap_or(1) - 1;
+                        col = (p.next().unwrap_or(1) - 1).min(cols - 1);
+                    }
+                    "K" => {
+                        let line = line(&mut rows, row);
+                        match n(0) {
+                            0 => line.truncate(col),
+                            1 => line.iter_mut().take(col + 1).for_each(|c| *c = Some(' ')),
+                            _ => line.clear(),
+                        }
+                    }
+                    "J" => match n(0) {
+                        0 => {
+                            line(&mut rows, row).truncate(col);
+                            rows.truncate(row + 1);
+                        }
+                        _ => rows.iter_mut().for_each(Vec::clear),
+                    },
+                    _ => {}
+                }
+            }
+            continue;
+        }
+        rest = &rest[c.len_utf8()..];
+        match c {
+            '\r' => col = 0,
+            '\n' => row += 1,
+            '\x08' => col = col.saturating_sub(1),
+            '\t' => col = ((col / 8 + 1) * 8).min(cols - 1),
+            c if c.is_control() => {}
+            c => {
+                let width = c.width().unwrap_or(0);
+                if width == 0 {
+                    continue;
+                }
+                if col + width > cols {
+                    row += 1;
+                    col = 0;
+                }
+                let line = line(&mut rows, row);
+                if line.len() < col + width {
+                    line.resize(col + width, Some(' '));
+                }
+                line[col] = Some(c);
+                if width == 2 {
+                    line[col + 1] = None;
+                }
+                col += width;
+            }
+        }
+    }
+    let mut screen: Vec<String> = rows.iter()
+        .map(|line| line.iter().flatten().collect::<String>().trim_end().to_string())
+        .collect();
+    while screen.last().is_some_and(String::is_empty) {
+        screen.pop();
+    }
+    screen
+}
+
+/// Length of the final byte of a control sequence without its introducer.
+fn command_len(csi: &str) -> usize {
+    csi.chars().next_back().map_or(0, char::len_utf8)
+}
+
+/// The row `row` of the screen, added if needed.
+fn line(rows: &mut Vec<Vec<Option<char>>>, row: usize) -> &mut Vec<Option<char>> {
+    if rows.len() <= row {
+        rows.resize(row + 1, Vec::new());
+    }
+    &mut rows[row]
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn screen() {
+        assert_eq!(super::screen("> ab\x08Xb\x08\r\n", 80), ["> aXb"]);
+        assert_eq!(super::screen("\r\x1b[J> a\r\x1b[3C\r\x1b[J> al\x1b[1;32m\x1b[0m\r\x1b[4C", 80), ["> al"]);
+        assert_eq!(super::screen("> abc\r\x1b[4Gd\x1b[K", 80), ["> ad"]);
+        assert_eq!(super::screen("abcdef", 4), ["abcd", "ef"]);
+        assert_eq!(super::screen("> 日本\r\n\r\n", 80), ["> 日本"]);
+        assert_eq!(super::screen("alpha\talpine\r\n> alp", 80), ["alpha   alpine", "> alp"]);
+    }
+}
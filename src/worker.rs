@@ -0,0 +1,140 @@
+//! Completion on a worker thread, with a timeout.
+
+use std::cell::RefCell;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::completion::{Completer, CompletionContext};
+
+/// A `Completer` running another one on a worker thread, so that a slow source does not freeze the line.
+///
+/// If the candidates are not given within the timeout, the candidates of the last finished completion
+/// are used when they are still relevant (same line before the word, which extends the word completed then),
+/// otherwise there is no candidate.
+/// Results arriving late are kept for the next completion:
+/// wrapping a `CachedCompleter` makes them available as soon as TAB is pressed again.
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use readline::{CachedCompleter, CompletionContext, ThreadedCompleter};
+///
+/// let remote = |context: &CompletionContext| vec![format!("{}_table", context.word)];
+/// let completer = ThreadedCompleter::new(CachedCompleter::new(remote, Duration::from_secs(60)),
+///                                        Duration::from_millis(200));
+/// readline::set_completer(Some(Box::new(completer)));
+/// ```
+///
+/// The wrapped completer must not use readline, which is not thread-safe: `FilenameCompleter` is rejected.
+///
+/// ```compile_fail
+/// use std::time::Duration;
+/// use readline::{FilenameCompleter, ThreadedCompleter};
+///
+/// ThreadedCompleter::new(FilenameCompleter::new(), Duration::from_millis(200));
+/// ```
+pub struct ThreadedCompleter {
+    requests: Sender<(u64, CompletionContext)>,
+    results: Receiver<(u64, CompletionContext, Vec<String>)>,
+    timeout: Duration,
+    state: RefCell<State>,
+}
+
+struct State {
+    id: u64,
+    last: Option<(CompletionContext, Vec<String>)>,
+}
+
+impl ThreadedCompleter {
+    /// Run `completer` on a new worker thread, waiting at most `timeout` for its candidates.
+    ///
+    /// The worker stops when the `ThreadedCompleter` is dropped and the current completion is done.
+    pub fn new<C>(completer: C, timeout: Duration) -> ThreadedCompleter where C: Completer + Send + 'static {
+        let (requests, worker_requests) = mpsc::channel::<(u64, CompletionContext)>();
+        let (worker_results, results) = mpsc::channel();
+        thread::spawn(move || {
+            while let Ok(mut request) = worker_requests.recv() {
+                // only the most recent completion matters
+                while let Ok(newer) = worker_requests.try_recv() {
+                    request = newer;
+                }
+                let (id, context) = request;
+                let entries = completer.complete(&context);
+                if worker_results.send((id, context, entries)).is_err() {
+                    break;
+                }
+            }
+        });
+        ThreadedCompleter { requests, results, timeout, state: RefCell::new(State { id: 0, last: None }) }
+    }
+}
+
+impl Completer for ThreadedCompleter {
+    fn complete(&self, context: &CompletionContext) -> Vec<String> {
+        let mut state = self.state.borrow_mut();
+        state.id += 1;
+        let id = state.id;
+        // the worker is gone if the completer panicked
+        if self.requests.send((id, context.clone())).is_ok() {
+            let deadline = Instant::now() + self.timeout;
+            loop {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                match self.results.recv_timeout(remaining) {
+                    Ok((result_id, result_context, entries)) => {
+                        if result_id == id {
+                            state.last = Some((result_context, entries.clone()));
+                            return entries;
+                        }
+                        state.last = Some((result_context, entries));
+                    }
+                    Err(_) => break,
+                }
+            }
+        }
+        match state.last {
+            Some((ref last, ref entries)) => partial(last, entries, context),
+            None => Vec::new(),
+        }
+    }
+}
+
+/// Return the `entries` given for the `last` context which still match `context`.
+fn partial(last: &CompletionContext, entries: &[String], context: &CompletionContext) -> Vec<String> {
+    if last.line[..last.start] != context.line[..context.start] || !context.word.starts_with(&last.word) {
+        return Vec::new();
+    }
+    entries.iter().filter(|e| e.starts_with(&context.word)).cloned().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    use super::ThreadedCompleter;
+    use super::super::completion::{Completer, CompletionContext};
+
+    #[test]
+    fn threaded_completer() {
+        // the slow completions wait until they are released, the timeout being observed meanwhile
+        let (release, released) = mpsc::channel::<()>();
+        let released = Mutex::new(released);
+        let completer = ThreadedCompleter::new(move |context: &CompletionContext| {
+            if context.word.starts_with("slow") {
+                let _ = released.lock().unwrap().recv();
+            }
+            vec![format!("{}1", context.word), format!("{}2", context.word)]
+        }, Duration::from_millis(500));
+
+        assert_eq!(completer.complete(&CompletionContext::new("get fast", 4, 8)), vec!["fast1", "fast2"]);
+        // timeout
+        assert!(completer.complete(&CompletionContext::new("get slow", 4, 8)).is_empty());
+        release.send(()).unwrap();
+        // timeout again, with the late results of the previous completion
+        assert_eq!(completer.complete(&CompletionContext::new("get slow1", 4, 9)), vec!["slow1"]);
+        assert!(completer.complete(&CompletionContext::new("set slow", 4, 8)).is_empty());
+        // the worker, waiting in the last completion, then stops
+        drop(release);
+    }
+}
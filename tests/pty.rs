@@ -0,0 +1,123 @@
+//! Line editing, history and completion on a pseudo-terminal.
+// libedit reads the streams of the first pty only
+#![cfg(all(feature = "testing", not(feature = "libedit")))]
+
+use std::ffi::CStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+
+use readline::testing::Pty;
+use readline::{CompletionContext, Interrupted};
+
+// readline is not thread-safe
+static LOCK: Mutex<()> = Mutex::new(());
+
+fn lock() -> MutexGuard<'static, ()> {
+    LOCK.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn words(context: &CompletionContext) -> Vec<String> {
+    ["alpha", "alpine", "beta"].iter().filter(|w| w.starts_with(&context.word)).map(|w| w.to_string()).collect()
+}
+
+#[test]
+fn editing() {
+    let _lock = lock();
+    let mut pty = Pty::new().unwrap();
+    let outcome = pty.readline("> ", "ab\x02X\r").unwrap();
+    assert_eq!(outcome.line.as_deref(), Some("aXb"));
+    assert_eq!(outcome.screen, ["> aXb"]);
+
+    let outcome = pty.readline("> ", "hello\x01\x0b\x04").unwrap();
+    assert_eq!(outcome.line, None);
+}
+
+#[test]
+fn history() {
+    let _lock = lock();
+    let mut pty = Pty::new().unwrap();
+    readline::clear_history();
+    readline::add_history("previous");
+    let outcome = pty.readline("> ", "\x1b[A\r").unwrap();
+    assert_eq!(outcome.line.as_deref(), Some("previous"));
+    readline::clear_history();
+}
+
+#[test]
+fn completion() {
+    let _lock = lock();
+    let mut pty = Pty::new().unwrap();
+    readline::set_completer(Some(Box::new(words)));
+
+    let outcome = pty.readline("> ", "be\t\r").unwrap();
+    assert_eq!(outcome.line.as_deref(), Some("beta "));
+    assert_eq!(outcome.screen, ["> beta"]);
+
+    // the second TAB lists the matches
+    let outcome = pty.readline("> ", "alp\t\th\r").unwrap();
+    assert_eq!(outcome.line.as_deref(), Some("alph"));
+    assert!(outcome.screen.iter().any(|row| row.contains("alpha") && row.contains("alpine")), "{:?}", outcome.screen);
+    assert_eq!(outcome.screen.last().map(String::as_str), Some("> alph"));
+
+    readline::set_completer(None);
+}
+
+static LINES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+extern "C" fn handler(line: *mut libc::c_char) {
+    if !line.is_null() {
+        let text = unsafe { std::ffi::CStr::from_ptr(line) }.to_string_lossy().into_owned();
+        LINES.lock().unwrap().push(text);
+        unsafe { libc::free(line as *mut libc::c_void) };
+    }
+}
+
+#[test]
+fn callback() {
+    let _lock = lock();
+    let mut pty = Pty::new().unwrap();
+    readline::rl_callback_handler_install("> ", Some(handler));
+    pty.type_keys("one\rtwo\r").unwrap();
+    // a call reads one key or all the pending ones, depending on the library
+    while LINES.lock().unwrap().len() < 2 {
+        readline::rl_callback_read_char();
+    }
+    readline::rl_callback_handler_remove();
+    assert_eq!(*LINES.lock().unwrap(), ["one", "two"]);
+    assert!(pty.take_output().windows(5).any(|w| w == b"> one"));
+}
+
+#[test]
+fn interrupt() {
+    let _lock = lock();
+    let mut pty = Pty::new().unwrap();
+    // The event hook is called while readline_interruptible waits for input, once the handler is installed:
+    // the signal is sent when the keys have been read.
+    static READ: AtomicBool = AtomicBool::new(false);
+    READ.store(false, Ordering::SeqCst);
+    readline::set_rl_event_hook(Some(Box::new(|| {
+        let line = unsafe { CStr::from_ptr(readline::rl_line_buffer()) };
+        if line.to_bytes() == b"abc" {
+            READ.store(true, Ordering::SeqCst);
+        }
+    })));
+    // Control-C typed on a pty which is not the controlling terminal sends no signal
+    let sigint = thread::spawn(|| {
+        while !READ.load(Ordering::SeqCst) {
+            thread::sleep(Duration::from_millis(1));
+        }
+        unsafe { libc::kill(libc::getpid(), libc::SIGINT) };
+    });
+    let outcome = pty.read_with("abc", || readline::readline_interruptible("> ")).unwrap();
+    sigint.join().unwrap();
+    readline::set_rl_event_hook(None);
+    assert_eq!(outcome.line, Err(Interrupted));
+    assert_eq!(outcome.screen, ["> abc^C"]);
+
+    // prompting again
+    let outcome = pty.read_with("ok\r", || readline::readline_interruptible("> ")).unwrap();
+    assert_eq!(outcome.line, Ok(Some("ok".to_string())));
+    assert_eq!(outcome.screen, ["> ok"]);
+}